@@ -0,0 +1,593 @@
+use std::{borrow::Cow, fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use futures_util::{
+    Stream,
+    future::{join_all, ready},
+    stream::FuturesUnordered,
+};
+use serde::Serialize;
+#[cfg(feature = "preprocess")]
+use tracing::warn;
+
+#[cfg(feature = "preprocess")]
+use crate::preprocess::Preprocessor;
+use crate::{
+    error::Error,
+    input::{Crop, DownloadOptions, ImageData, ImageInput, UploadLimits},
+    make_client,
+    resolve::{BoardLogin, Resolver},
+    score::{Calibrated, Confidence, Scorer},
+    source::{Item, Output, Source, SourceId},
+};
+
+/// The result of searching a single source, tagged with the name of that source.
+pub type Checked = (SourceId, Result<Output, Error>);
+
+/// A work found by one or more sources, from [`merge`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Merged {
+    /// The item the most confident source found, with anything it's missing filled in from the others.
+    pub item: Item,
+    /// The sources that found the work, from most to least confident.
+    pub sources: Vec<SourceId>,
+    /// How sure the [`Scorer`] is of the work, from 0 to 1, combining every source that found it.
+    ///
+    /// Works that several sources agree on score above works only one source found.
+    pub score: f32,
+    /// The bucket [`Merged::score`] falls in.
+    pub confidence: Confidence,
+}
+
+/// Groups the items from every source by the work they point to, see [`Item::same_work`],
+/// and ranks them from best to worst [`Merged::score`], using the [`Calibrated`] scorer.
+///
+/// Failed sources are skipped.
+#[must_use]
+pub fn merge<'a>(results: impl IntoIterator<Item = &'a Checked>) -> Vec<Merged> {
+    merge_with(results, &Calibrated::default())
+}
+
+/// Like [`merge`], but with the given [`Scorer`].
+#[must_use]
+pub fn merge_with<'a>(
+    results: impl IntoIterator<Item = &'a Checked>,
+    scorer: &impl Scorer,
+) -> Vec<Merged> {
+    let mut found = results
+        .into_iter()
+        .filter_map(|(source, res)| Some((*source, res.as_ref().ok()?)))
+        .flat_map(|(source, output)| output.items.iter().map(move |item| (source, item)))
+        .map(|(source, item)| (source, item, scorer.confidence(source, item)))
+        .collect::<Vec<_>>();
+
+    // Most confident first, so the first item of each group is the one that's kept,
+    // and only the best match from each source counts towards the score
+    found.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+
+    let mut merged: Vec<(Merged, Vec<f32>)> = Vec::new();
+
+    for (source, item, confidence) in found {
+        match merged.iter_mut().find(|(m, _)| m.item.same_work(item)) {
+            Some((existing, confidences)) => {
+                let kept = &mut existing.item;
+                kept.title = kept.title.take().or_else(|| item.title.clone());
+                kept.author = kept.author.take().or_else(|| item.author.clone());
+                kept.site = kept.site.take().or_else(|| item.site.clone());
+                kept.site_ref = kept.site_ref.take().or_else(|| item.site_ref.clone());
+                kept.original_source = kept
+                    .original_source
+                    .take()
+                    .or_else(|| item.original_source.clone());
+
+                if !existing.sources.contains(&source) {
+                    existing.sources.push(source);
+                    confidences.push(confidence);
+                }
+            }
+            None => merged.push((
+                Merged {
+                    item: item.clone(),
+                    sources: vec![source],
+                    score: 0.0,
+                    confidence: Confidence::Low,
+                },
+                vec![confidence],
+            )),
+        }
+    }
+
+    let mut merged = merged
+        .into_iter()
+        .map(|(m, confidences)| {
+            let score = scorer.combine(&confidences);
+
+            Merged {
+                score,
+                confidence: scorer.bucket(score),
+                ..m
+            }
+        })
+        .collect::<Vec<_>>();
+
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    merged
+}
+
+/// A [`Source`] that can be stored alongside sources of other types.
+///
+/// Implemented for every [`Source`], there's no need to implement it by hand.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait AnySource: Debug + Send + Sync {
+    /// The [`Source::id`] of the source.
+    fn id(&self) -> SourceId;
+
+    /// The [`Source::UPLOAD_LIMITS`] of the source.
+    fn upload_limits(&self) -> UploadLimits;
+
+    /// The [`Source::board_login`] of the source.
+    fn board_login(&self) -> Option<BoardLogin> {
+        None
+    }
+
+    /// Calls [`Source::check_input`].
+    async fn search(&self, input: &ImageInput) -> Result<Output, Error>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S> AnySource for S
+where
+    S: Source + Debug + Send + Sync,
+{
+    fn id(&self) -> SourceId {
+        Source::id(self)
+    }
+
+    fn upload_limits(&self) -> UploadLimits {
+        S::UPLOAD_LIMITS
+    }
+
+    fn board_login(&self) -> Option<BoardLogin> {
+        Source::board_login(self)
+    }
+
+    async fn search(&self, input: &ImageInput) -> Result<Output, Error> {
+        self.check_input(input).await
+    }
+}
+
+/// Searches several sources at once.
+///
+/// By default each source is given the URL and fetches the image by itself.
+/// With [`Aggregator::download_once`], the image is instead downloaded a single time
+/// and uploaded to every source, which is more reliable when the origin is slow or flaky.
+#[derive(Debug, Default)]
+pub struct Aggregator {
+    sources: Vec<Box<dyn AnySource>>,
+    download: Option<DownloadOptions>,
+    resolve_originals: bool,
+    /// Taken from each source's [`AnySource::board_login`], for [`Aggregator::resolve_originals`].
+    logins: Vec<BoardLogin>,
+    #[cfg(feature = "preprocess")]
+    preprocessor: Option<Preprocessor>,
+}
+
+impl Aggregator {
+    /// Creates an empty [`Aggregator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source to search.
+    #[must_use]
+    pub fn with_source(self, source: impl AnySource + 'static) -> Self {
+        self.with_boxed_source(Box::new(source))
+    }
+
+    /// Adds a source that's already boxed, such as one from [`source_by_name`].
+    #[must_use]
+    pub fn with_boxed_source(mut self, source: Box<dyn AnySource>) -> Self {
+        self.logins.extend(source.board_login());
+        self.sources.push(source);
+        self
+    }
+
+    /// Downloads the image once, within the given limits, and uploads the bytes to every source.
+    #[must_use]
+    pub fn download_once(mut self, options: DownloadOptions) -> Self {
+        self.download = Some(options);
+        self
+    }
+
+    /// Looks up where each image board post in the results came from, see [`resolve::originals`](crate::resolve::originals).
+    ///
+    /// This takes a request to the board for each post that doesn't already say, so it's off by default.
+    /// Posts found by several sources are only looked up once, using the login of a
+    /// [`Booru`](crate::source::booru::Booru) source for the same board if one was added.
+    #[must_use]
+    pub const fn resolve_originals(mut self) -> Self {
+        self.resolve_originals = true;
+        self
+    }
+
+    /// Fits downloaded images within each source's [`UploadLimits`] before uploading them.
+    #[cfg(feature = "preprocess")]
+    #[must_use]
+    pub const fn preprocess(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessor = Some(preprocessor);
+        self
+    }
+
+    /// The names of the sources that will be searched, in the order they were added.
+    pub fn sources(&self) -> impl Iterator<Item = SourceId> + '_ {
+        self.sources.iter().map(|s| s.id())
+    }
+
+    /// The limits for downloading the image, from [`Aggregator::download_once`] or the defaults.
+    #[cfg(feature = "server")]
+    pub(crate) fn download_options(&self) -> DownloadOptions {
+        self.download.clone().unwrap_or_default()
+    }
+
+    /// Turns a URL into the [`ImageInput`] handed to each source, downloading it if configured to.
+    ///
+    /// # Errors
+    /// Any error from [`ImageData::download`].
+    pub async fn prepare(&self, url: &str) -> Result<ImageInput, Error> {
+        match &self.download {
+            Some(options) => Ok(ImageData::download(&make_client(), url, options)
+                .await?
+                .into()),
+            None => Ok(ImageInput::Url(url.to_string())),
+        }
+    }
+
+    /// Searches every source for the given URL.
+    ///
+    /// # Errors
+    /// Only if the image had to be downloaded and that failed, see [`Aggregator::prepare`].
+    /// Errors from individual sources are returned alongside their name.
+    pub async fn check(&self, url: &str) -> Result<Vec<Checked>, Error> {
+        let input = self.prepare(url).await?;
+
+        Ok(self.check_input(&input).await)
+    }
+
+    /// Searches every source concurrently with the given input.
+    ///
+    /// If the [`Preprocessor`] crops the image, the rectangle is reported in each [`Output::crop`].
+    pub async fn check_input(&self, input: &ImageInput) -> Vec<Checked> {
+        self.check_input_paced(input, |_| ready(())).await
+    }
+
+    /// Like [`Aggregator::check_input`], but waits for `pace` before searching each source.
+    pub(crate) async fn check_input_paced<F, Fut>(
+        &self,
+        input: &ImageInput,
+        pace: F,
+    ) -> Vec<Checked>
+    where
+        F: Fn(SourceId) -> Fut + Clone + Send,
+        Fut: Future<Output = ()> + Send,
+    {
+        join_all(self.searches(Cow::Borrowed(input), pace)).await
+    }
+
+    /// Searches every source for the given URL, yielding each result as soon as that source answers.
+    ///
+    /// # Errors
+    /// Only if the image had to be downloaded and that failed, see [`Aggregator::prepare`].
+    /// Errors from individual sources are yielded alongside their name.
+    pub async fn check_stream(&self, url: &str) -> Result<impl Stream<Item = Checked> + '_, Error> {
+        let input = self.prepare(url).await?;
+
+        Ok(self
+            .searches(Cow::Owned(input), |_| ready(()))
+            .collect::<FuturesUnordered<_>>())
+    }
+
+    /// Searches every source concurrently with the given input, yielding each result as soon as that source answers.
+    ///
+    /// Results come in the order the sources finish rather than the order they were added,
+    /// so the fast sources can be shown while the slow ones are still going.
+    pub fn check_input_stream<'a>(
+        &'a self,
+        input: &'a ImageInput,
+    ) -> impl Stream<Item = Checked> + 'a {
+        self.searches(Cow::Borrowed(input), |_| ready(()))
+            .collect::<FuturesUnordered<_>>()
+    }
+
+    /// A future searching each source with `input`, waiting for `pace` first.
+    fn searches<'a, F, Fut>(
+        &'a self,
+        input: Cow<'a, ImageInput>,
+        pace: F,
+    ) -> impl Iterator<Item = impl Future<Output = Checked> + 'a> + 'a
+    where
+        F: Fn(SourceId) -> Fut + Clone + Send + 'a,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (input, crop) = self.crop(input);
+        // Shared, as the searches may outlive the caller's borrow when streamed
+        let input = Arc::new(input);
+        let resolver = self
+            .resolve_originals
+            .then(|| Arc::new(Resolver::new(self.logins.clone())));
+
+        self.sources.iter().map(move |source| {
+            let input = Arc::clone(&input);
+            let resolver = resolver.clone();
+            let pace = pace.clone();
+
+            async move {
+                pace(source.id()).await;
+
+                let mut res = self.search(&**source, &input).await;
+
+                if let Ok(output) = &mut res {
+                    output.crop = crop;
+
+                    if let Some(resolver) = &resolver {
+                        resolver.originals(&mut output.items).await;
+                    }
+                }
+
+                (source.id(), res)
+            }
+        })
+    }
+
+    /// Crops the image if the [`Preprocessor`] is set to, returning the rectangle that's left.
+    #[allow(clippy::unused_self, clippy::missing_const_for_fn)]
+    fn crop<'a>(&self, input: Cow<'a, ImageInput>) -> (Cow<'a, ImageInput>, Option<Crop>) {
+        #[cfg(feature = "preprocess")]
+        if let (Some(preprocessor), ImageInput::Data(data)) = (&self.preprocessor, input.as_ref()) {
+            match preprocessor.crop(data) {
+                Ok((data, crop)) => return (Cow::Owned(data.into()), crop),
+                Err(e) => warn!(?e, "Failed to crop, searching the whole image"),
+            }
+        }
+
+        (input, None)
+    }
+
+    /// Searches a single source, preprocessing the image for it first if configured to.
+    async fn search(&self, source: &dyn AnySource, input: &ImageInput) -> Result<Output, Error> {
+        #[cfg(feature = "preprocess")]
+        if let (Some(preprocessor), ImageInput::Data(data)) = (&self.preprocessor, input) {
+            let data = preprocessor.prepare(data, &source.upload_limits())?;
+
+            return source.search(&ImageInput::Data(data)).await;
+        }
+
+        source.search(input).await
+    }
+}
+
+/// The names [`source_by_name`] accepts, for the sources enabled at build time.
+///
+/// Every source depends on a feature, so with few features enabled there may be none.
+#[must_use]
+#[allow(unused_mut, clippy::vec_init_then_push, clippy::missing_const_for_fn)]
+pub fn source_names() -> Vec<SourceId> {
+    let mut names = Vec::new();
+
+    #[cfg(feature = "iqdb")]
+    names.push("iqdb");
+    #[cfg(feature = "saucenao")]
+    names.push("saucenao");
+    #[cfg(feature = "fuzzysearch")]
+    names.push("fuzzysearch");
+    #[cfg(feature = "ascii2d")]
+    names.push("ascii2d");
+    #[cfg(feature = "bing")]
+    names.push("bing");
+    #[cfg(feature = "booru")]
+    names.extend(["danbooru", "e621"]);
+    #[cfg(feature = "ehentai")]
+    names.push("ehentai");
+    #[cfg(feature = "fluffle")]
+    names.push("fluffle");
+    #[cfg(feature = "iqdb_server")]
+    names.push("iqdb_server");
+    #[cfg(feature = "local")]
+    names.push("local");
+    #[cfg(feature = "tracemoe")]
+    names.push("tracemoe");
+    #[cfg(feature = "yandex")]
+    names.push("yandex");
+
+    names
+}
+
+/// Creates the built-in source called `name`, one of [`source_names`], with its default settings.
+///
+/// `setting` is asked for anything the source needs, by name:
+/// - `saucenao_api_key`, `fuzzysearch_api_key` and `iqdb_server_url`, which are required
+/// - `tracemoe_api_key`, which raises the trace.moe quota
+/// - `local_index`, the path to an [`Index`](crate::source::local::Index) file, which is required
+/// - `ehentai_member_id`, `ehentai_pass_hash` and `ehentai_igneous`, the cookies for searching `ExHentai`
+///
+/// # Errors
+/// - [`Error::Generic`] if there's no source called `name`, or a required setting is missing
+/// - Any error from creating the source
+#[allow(unused_variables, unreachable_code, clippy::unused_async)]
+pub async fn source_by_name(
+    name: &str,
+    setting: impl Fn(&str) -> Option<String> + Sync,
+) -> Result<Box<dyn AnySource>, Error> {
+    let require =
+        |key: &str| setting(key).ok_or_else(|| Error::Generic(format!("`{key}` isn't set")));
+
+    let source: Box<dyn AnySource> = match name {
+        #[cfg(feature = "iqdb")]
+        "iqdb" => Box::new(crate::source::iqdb::Iqdb::create(()).await?),
+        #[cfg(feature = "saucenao")]
+        "saucenao" => {
+            Box::new(crate::source::saucenao::SauceNao::create(require("saucenao_api_key")?).await?)
+        }
+        #[cfg(feature = "fuzzysearch")]
+        "fuzzysearch" => Box::new(
+            crate::source::fuzzysearch::FuzzySearch::create(require("fuzzysearch_api_key")?)
+                .await?,
+        ),
+        #[cfg(feature = "ascii2d")]
+        "ascii2d" => Box::new(crate::source::ascii2d::Ascii2d::create(()).await?),
+        #[cfg(feature = "bing")]
+        "bing" => Box::new(crate::source::bing::Bing::create(()).await?),
+        #[cfg(feature = "booru")]
+        "danbooru" | "e621" => {
+            use crate::source::booru::{Booru, Config};
+
+            let config = if name == "danbooru" {
+                Config::danbooru()
+            } else {
+                Config::e621()
+            };
+
+            Box::new(Booru::create(config).await?)
+        }
+        #[cfg(feature = "ehentai")]
+        "ehentai" => {
+            use crate::source::ehentai::{Config, Cookies, EHentai};
+
+            let cookies = match (setting("ehentai_member_id"), setting("ehentai_pass_hash")) {
+                (Some(ipb_member_id), Some(ipb_pass_hash)) => Some(Cookies {
+                    ipb_member_id,
+                    ipb_pass_hash,
+                    igneous: setting("ehentai_igneous"),
+                }),
+                _ => None,
+            };
+
+            Box::new(
+                EHentai::create(Config {
+                    cookies,
+                    ..Config::default()
+                })
+                .await?,
+            )
+        }
+        #[cfg(feature = "fluffle")]
+        "fluffle" => Box::new(
+            crate::source::fluffle::Fluffle::create(crate::source::fluffle::Config::default())
+                .await?,
+        ),
+        #[cfg(feature = "iqdb_server")]
+        "iqdb_server" => {
+            use crate::source::iqdb_server::{Config, IqdbServer};
+
+            Box::new(IqdbServer::create(Config::new(require("iqdb_server_url")?)).await?)
+        }
+        #[cfg(feature = "local")]
+        "local" => {
+            use crate::source::local::{Config, Index, LocalIndex};
+
+            let path = require("local_index")?;
+            let index = Index::load(&path)
+                .map_err(|e| Error::Generic(format!("Couldn't load {path}: {e}")))?;
+
+            Box::new(LocalIndex::create(Config::new(index)).await?)
+        }
+        #[cfg(feature = "tracemoe")]
+        "tracemoe" => {
+            use crate::source::tracemoe::{Config, TraceMoe};
+
+            Box::new(
+                TraceMoe::create(Config {
+                    api_key: setting("tracemoe_api_key"),
+                    ..Config::default()
+                })
+                .await?,
+            )
+        }
+        #[cfg(feature = "yandex")]
+        "yandex" => Box::new(crate::source::yandex::Yandex::create(()).await?),
+        _ => {
+            return Err(Error::Generic(format!(
+                "Unknown source `{name}`. Available: {}",
+                source_names().join(", ")
+            )));
+        }
+    };
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{source_by_name, source_names};
+    use crate::error::Error;
+
+    #[tokio::test]
+    async fn source_by_name_knows_every_source_name() {
+        for name in source_names() {
+            // Every setting is given, so only sources that load something can fail
+            let res = source_by_name(name, |_| Some("key".to_string())).await;
+
+            if name != "local" {
+                let source = res.expect("the source should be created");
+                assert_eq!(source.id(), name);
+            }
+        }
+    }
+
+    #[cfg(feature = "booru")]
+    #[tokio::test]
+    async fn collects_board_logins_from_every_source() {
+        use super::Aggregator;
+        use crate::source::{
+            Source,
+            booru::{Booru, Config},
+        };
+
+        let danbooru = Booru::create(Config::danbooru().login("user", "key"))
+            .await
+            .expect("the source should be created");
+        let e621 = Booru::create(Config::e621().login("other", "secret"))
+            .await
+            .expect("the source should be created");
+        let anonymous = Booru::create(Config::danbooru().name("anonymous"))
+            .await
+            .expect("the source should be created");
+
+        let aggregator = Aggregator::new()
+            .with_source(danbooru)
+            .with_boxed_source(Box::new(e621))
+            .with_source(anonymous);
+
+        let logins = aggregator
+            .logins
+            .iter()
+            .map(|login| (login.site, login.username.as_str(), login.api_key.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logins,
+            [("danbooru", "user", "key"), ("e621", "other", "secret")]
+        );
+    }
+
+    #[tokio::test]
+    async fn source_by_name_rejects_unknown_names() {
+        let Err(Error::Generic(message)) = source_by_name("nope", |_| None).await else {
+            panic!("expected an error");
+        };
+
+        assert!(message.contains("nope"));
+    }
+
+    #[cfg(feature = "saucenao")]
+    #[tokio::test]
+    async fn source_by_name_names_missing_settings() {
+        let Err(Error::Generic(message)) = source_by_name("saucenao", |_| None).await else {
+            panic!("expected an error");
+        };
+
+        assert!(message.contains("saucenao_api_key"));
+    }
+}
@@ -0,0 +1,535 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures_timer::Delay;
+use futures_util::{Stream, StreamExt, future::ready};
+use tracing::{debug, warn};
+
+use crate::{
+    aggregate::{Aggregator, Checked},
+    error::Error,
+    input::ImageInput,
+    source::SourceId,
+};
+
+/// The results of searching one input of a [`Batch`], tagged with where the input came from.
+///
+/// The outer error is for inputs that couldn't be searched at all, such as a failed download.
+/// Errors from individual sources are returned alongside their name, as with [`Aggregator::check`].
+pub type Batched = (String, Result<Vec<Checked>, Error>);
+
+/// Searches many inputs with an [`Aggregator`], a few at a time.
+///
+/// Results are yielded as a [`Stream`], in the order they finish rather than the order of the inputs.
+/// [`Batch::pace`] keeps each source within its rate limit however many inputs are in flight,
+/// and [`Batch::checkpoint`] records finished inputs so an interrupted run can pick up where it left off.
+#[derive(Debug)]
+pub struct Batch {
+    aggregator: Aggregator,
+    concurrency: usize,
+    pacers: HashMap<SourceId, Pacer>,
+    checkpoint: Option<Checkpoint>,
+    progress: Counters,
+}
+
+/// How far along a [`Batch`] is, from [`Batch::progress`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Inputs that have started being searched.
+    pub started: usize,
+    /// Inputs that have finished being searched, including those that failed.
+    pub finished: usize,
+    /// Inputs that couldn't be searched at all, or that some source failed on.
+    pub failed: usize,
+    /// Inputs that were skipped, as the checkpoint says they're already done.
+    pub skipped: usize,
+}
+
+impl Progress {
+    /// Inputs that are being searched right now.
+    #[must_use]
+    pub const fn in_flight(&self) -> usize {
+        self.started.saturating_sub(self.finished)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    started: AtomicUsize,
+    finished: AtomicUsize,
+    failed: AtomicUsize,
+    skipped: AtomicUsize,
+}
+
+/// Spaces out searches of a single source.
+#[derive(Debug)]
+struct Pacer {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Pacer {
+    /// Waits until it's this search's turn.
+    async fn wait(&self) {
+        let at = {
+            // A panic while holding the lock can't leave the time in a bad state
+            let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+
+        let delay = at.saturating_duration_since(Instant::now());
+        if !delay.is_zero() {
+            Delay::new(delay).await;
+        }
+    }
+}
+
+/// The inputs that are already done, and the file they're recorded in.
+#[derive(Debug)]
+struct Checkpoint {
+    done: HashSet<String>,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    fn open(path: &Path) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+
+        // A run that was cut off mid-write leaves half a line, which the next record would be appended to
+        let complete = text.rfind('\n').map_or(0, |end| end + 1);
+        if complete < text.len() {
+            warn!(
+                partial = &text[complete..],
+                "Dropping an unfinished line from the checkpoint"
+            );
+            file.set_len(complete as u64)?;
+        }
+
+        let done = text[..complete]
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(ToString::to_string)
+            .collect();
+
+        Ok(Self {
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn record(&self, origin: &str) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+
+        writeln!(file, "{origin}")
+    }
+}
+
+impl Batch {
+    /// Creates a [`Batch`] that searches with `aggregator`, four inputs at a time.
+    #[must_use]
+    pub fn new(aggregator: Aggregator) -> Self {
+        Self {
+            aggregator,
+            concurrency: 4,
+            pacers: HashMap::new(),
+            checkpoint: None,
+            progress: Counters::default(),
+        }
+    }
+
+    /// Sets how many inputs are searched at once. Values below one are treated as one.
+    #[must_use]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Searches `source` at most once per `interval`, however many inputs are in flight.
+    ///
+    /// Sources without a pace are searched as soon as their input comes up.
+    #[must_use]
+    pub fn pace(mut self, source: SourceId, interval: Duration) -> Self {
+        self.pacers.insert(
+            source,
+            Pacer {
+                interval,
+                next: Mutex::new(Instant::now()),
+            },
+        );
+        self
+    }
+
+    /// Records each finished input in the file at `path`, one per line, and skips inputs that are already in it.
+    ///
+    /// An input only counts as finished if every source searched it without an error,
+    /// so inputs that failed are tried again on the next run.
+    /// Inputs are told apart by [`ImageInput::origin`].
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the file exists but couldn't be read, or couldn't be opened for writing
+    pub fn checkpoint(mut self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        self.checkpoint = Some(Checkpoint::open(path.as_ref())?);
+
+        Ok(self)
+    }
+
+    /// How far along the batch is. Can be called while [`Batch::run`] is going.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            started: self.progress.started.load(Ordering::Relaxed),
+            finished: self.progress.finished.load(Ordering::Relaxed),
+            failed: self.progress.failed.load(Ordering::Relaxed),
+            skipped: self.progress.skipped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Searches each of `inputs`, yielding the results as each one finishes.
+    ///
+    /// Takes a [`Stream`], use [`futures_util::stream::iter`] for anything iterable.
+    /// Links are handled as in [`Aggregator::check`], and images as in [`Aggregator::check_input`].
+    pub fn run<'a, I>(
+        &'a self,
+        inputs: impl Stream<Item = I> + 'a,
+    ) -> impl Stream<Item = Batched> + 'a
+    where
+        I: Into<ImageInput> + 'a,
+    {
+        inputs
+            .map(Into::into)
+            .filter(move |input: &ImageInput| {
+                let done = self
+                    .checkpoint
+                    .as_ref()
+                    .is_some_and(|checkpoint| checkpoint.done.contains(input.origin()));
+
+                if done {
+                    debug!(origin = input.origin(), "Skipping, already done");
+                    self.progress.skipped.fetch_add(1, Ordering::Relaxed);
+                }
+
+                ready(!done)
+            })
+            .map(move |input| self.search(input))
+            .buffer_unordered(self.concurrency)
+    }
+
+    async fn search(&self, input: ImageInput) -> Batched {
+        self.progress.started.fetch_add(1, Ordering::Relaxed);
+
+        // Only links need preparing, images are searched as they are
+        let prepared = match &input {
+            ImageInput::Url(url) => self.aggregator.prepare(url).await.map(Some),
+            ImageInput::Data(_) => Ok(None),
+        };
+
+        let results = match prepared {
+            Ok(prepared) => Ok(self
+                .aggregator
+                .check_input_paced(prepared.as_ref().unwrap_or(&input), |source| {
+                    self.wait(source)
+                })
+                .await),
+            Err(e) => Err(e),
+        };
+
+        let ok = results
+            .as_ref()
+            .is_ok_and(|results| results.iter().all(|(_, res)| res.is_ok()));
+
+        if ok {
+            if let Some(checkpoint) = &self.checkpoint
+                && let Err(e) = checkpoint.record(input.origin())
+            {
+                warn!(?e, "Failed to record the input in the checkpoint");
+            }
+        } else {
+            self.progress.failed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.progress.finished.fetch_add(1, Ordering::Relaxed);
+
+        (input.origin().to_string(), results)
+    }
+
+    async fn wait(&self, source: SourceId) {
+        if let Some(pacer) = self.pacers.get(source) {
+            pacer.wait().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use async_trait::async_trait;
+    use futures_timer::Delay;
+    use futures_util::{StreamExt, future::join_all, stream};
+
+    use super::{Batch, Pacer, Progress};
+    use crate::{
+        aggregate::{Aggregator, AnySource},
+        error::Error,
+        input::{ImageInput, UploadLimits},
+        source::{Output, SourceId},
+    };
+
+    /// A source that records what it was asked to search, failing on some inputs.
+    #[derive(Debug, Default)]
+    struct Stub {
+        fail: Vec<&'static str>,
+        delay: Duration,
+        searched: Mutex<Vec<String>>,
+        running: AtomicUsize,
+        most_running: AtomicUsize,
+    }
+
+    impl Stub {
+        fn searched(&self) -> Vec<String> {
+            let mut searched = self.searched.lock().expect("not poisoned").clone();
+            searched.sort();
+            searched
+        }
+    }
+
+    #[derive(Debug)]
+    struct Shared(Arc<Stub>);
+
+    #[async_trait]
+    impl AnySource for Shared {
+        fn id(&self) -> SourceId {
+            "stub"
+        }
+
+        fn upload_limits(&self) -> UploadLimits {
+            UploadLimits::DEFAULT
+        }
+
+        async fn search(&self, input: &ImageInput) -> Result<Output, Error> {
+            let running = self.0.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.0.most_running.fetch_max(running, Ordering::SeqCst);
+
+            Delay::new(self.0.delay).await;
+
+            self.0.running.fetch_sub(1, Ordering::SeqCst);
+            let origin = input.origin().to_string();
+            self.0
+                .searched
+                .lock()
+                .expect("not poisoned")
+                .push(origin.clone());
+
+            if self.0.fail.contains(&origin.as_str()) {
+                return Err(Error::Generic("stub failure".to_string()));
+            }
+
+            Ok(Output {
+                original_url: origin,
+                items: Vec::new(),
+                crop: None,
+            })
+        }
+    }
+
+    fn batch(stub: &Arc<Stub>) -> Batch {
+        Batch::new(Aggregator::new().with_source(Shared(Arc::clone(stub))))
+    }
+
+    /// A checkpoint path of its own for each test, starting with `contents`.
+    fn checkpoint(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("sauce-batch-{name}-{}.txt", std::process::id()));
+        fs::write(&path, contents).expect("writing should work");
+        path
+    }
+
+    fn read_lines(path: &PathBuf) -> Vec<String> {
+        let mut lines: Vec<String> = fs::read_to_string(path)
+            .expect("reading should work")
+            .lines()
+            .map(ToString::to_string)
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    async fn run(batch: &Batch, inputs: &[&'static str]) -> Vec<String> {
+        let mut origins: Vec<String> = batch
+            .run(stream::iter(inputs.iter().copied()))
+            .map(|(origin, _)| origin)
+            .collect()
+            .await;
+        origins.sort();
+        origins
+    }
+
+    #[tokio::test]
+    async fn resumes_skipping_inputs_in_the_checkpoint() {
+        let path = checkpoint("resume", "https://a.example/1\nhttps://a.example/2\n");
+        let stub = Arc::new(Stub::default());
+        let batch = batch(&stub).checkpoint(&path).expect("opening should work");
+
+        let origins = run(
+            &batch,
+            &[
+                "https://a.example/1",
+                "https://a.example/2",
+                "https://a.example/3",
+            ],
+        )
+        .await;
+        let lines = read_lines(&path);
+        fs::remove_file(&path).expect("cleaning up should work");
+
+        assert_eq!(origins, ["https://a.example/3"]);
+        assert_eq!(stub.searched(), ["https://a.example/3"]);
+        assert_eq!(batch.progress().skipped, 2);
+        assert_eq!(
+            lines,
+            [
+                "https://a.example/1",
+                "https://a.example/2",
+                "https://a.example/3"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn only_records_inputs_every_source_searched() {
+        let path = checkpoint("failures", "");
+        let stub = Arc::new(Stub {
+            fail: vec!["https://a.example/bad"],
+            ..Stub::default()
+        });
+        let batch = batch(&stub).checkpoint(&path).expect("opening should work");
+
+        run(&batch, &["https://a.example/good", "https://a.example/bad"]).await;
+        let lines = read_lines(&path);
+        fs::remove_file(&path).expect("cleaning up should work");
+
+        assert_eq!(lines, ["https://a.example/good"]);
+        assert_eq!(batch.progress().failed, 1);
+    }
+
+    #[tokio::test]
+    async fn drops_a_partially_written_last_line() {
+        let path = checkpoint("partial", "https://a.example/1\nhttps://a.exa");
+        let stub = Arc::new(Stub::default());
+        let batch = batch(&stub).checkpoint(&path).expect("opening should work");
+
+        run(&batch, &["https://a.example/1", "https://a.example/2"]).await;
+        let text = fs::read_to_string(&path).expect("reading should work");
+        fs::remove_file(&path).expect("cleaning up should work");
+
+        assert_eq!(stub.searched(), ["https://a.example/2"]);
+        assert_eq!(text, "https://a.example/1\nhttps://a.example/2\n");
+    }
+
+    #[tokio::test]
+    async fn starts_a_checkpoint_that_doesnt_exist() {
+        let path = checkpoint("missing", "");
+        fs::remove_file(&path).expect("removing should work");
+        let stub = Arc::new(Stub::default());
+        let batch = batch(&stub).checkpoint(&path).expect("opening should work");
+
+        run(&batch, &["https://a.example/1"]).await;
+        let lines = read_lines(&path);
+        fs::remove_file(&path).expect("cleaning up should work");
+
+        assert_eq!(lines, ["https://a.example/1"]);
+    }
+
+    #[tokio::test]
+    async fn pacer_spaces_out_searches() {
+        let pacer = Pacer {
+            interval: Duration::from_millis(50),
+            next: Mutex::new(Instant::now()),
+        };
+        let start = Instant::now();
+
+        let mut waited = join_all((0..3).map(|_| async {
+            pacer.wait().await;
+            start.elapsed()
+        }))
+        .await;
+        waited.sort();
+
+        assert!(waited[0] < Duration::from_millis(40), "{waited:?}");
+        assert!(waited[1] >= Duration::from_millis(50), "{waited:?}");
+        assert!(waited[2] >= Duration::from_millis(100), "{waited:?}");
+    }
+
+    #[tokio::test]
+    async fn paces_sources_across_inputs_in_flight() {
+        let stub = Arc::new(Stub::default());
+        let batch = batch(&stub)
+            .concurrency(4)
+            .pace("stub", Duration::from_millis(40));
+        let start = Instant::now();
+
+        run(&batch, &["1", "2", "3", "4"]).await;
+
+        assert!(
+            start.elapsed() >= Duration::from_millis(120),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_progress_under_concurrency() {
+        let stub = Arc::new(Stub {
+            fail: vec!["3", "7"],
+            delay: Duration::from_millis(5),
+            ..Stub::default()
+        });
+        let batch = batch(&stub).concurrency(3);
+        let inputs = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+        let mut in_flight = Vec::new();
+        let mut results = batch.run(stream::iter(inputs));
+        while results.next().await.is_some() {
+            in_flight.push(batch.progress().in_flight());
+        }
+        drop(results);
+
+        assert_eq!(
+            batch.progress(),
+            Progress {
+                started: 10,
+                finished: 10,
+                failed: 2,
+                skipped: 0,
+            }
+        );
+        assert!(in_flight.iter().all(|n| *n <= 3), "{in_flight:?}");
+        assert_eq!(stub.most_running.load(Ordering::SeqCst), 3);
+        assert_eq!(stub.searched().len(), 10);
+    }
+}
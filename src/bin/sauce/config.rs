@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// The config file, by default at `$XDG_CONFIG_HOME/sauce/config.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct File {
+    /// The sources to search when none are given on the command line.
+    pub sources: Vec<String>,
+    /// Keys and settings, overridden by flags and environment variables.
+    #[serde(flatten)]
+    pub keys: Keys,
+}
+
+impl File {
+    /// Reads the config file at `path`, or at the default location if there is none.
+    ///
+    /// A missing file at the default location is fine, and gives an empty config.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(format!("couldn't read {}: {e}", path.display())),
+        };
+
+        toml::from_str(&text).map_err(|e| format!("couldn't parse {}: {e}", path.display()))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(base.join("sauce").join("config.toml"))
+}
+
+/// API keys and other settings that some sources need.
+///
+/// Each can be given as a flag, an environment variable, or in the config file, in that order of preference.
+#[derive(Debug, Default, clap::Args, Deserialize)]
+#[serde(default)]
+pub struct Keys {
+    /// The API key for saucenao.com.
+    #[cfg(feature = "saucenao")]
+    #[arg(long, env = "SAUCENAO_API_KEY", hide_env_values = true)]
+    pub saucenao_api_key: Option<String>,
+
+    /// The API key for fuzzysearch.net.
+    #[cfg(feature = "fuzzysearch")]
+    #[arg(long, env = "FUZZYSEARCH_API_KEY", hide_env_values = true)]
+    pub fuzzysearch_api_key: Option<String>,
+
+    /// The API key for trace.moe, which raises the quota.
+    #[cfg(feature = "tracemoe")]
+    #[arg(long, env = "TRACEMOE_API_KEY", hide_env_values = true)]
+    pub tracemoe_api_key: Option<String>,
+
+    /// The base URL of a self-hosted iqdb server, for the `iqdb_server` source.
+    #[cfg(feature = "iqdb_server")]
+    #[arg(long, env = "IQDB_SERVER_URL")]
+    pub iqdb_server_url: Option<String>,
+
+    /// The index file for the `local` source.
+    #[cfg(feature = "local")]
+    #[arg(long, env = "SAUCE_LOCAL_INDEX")]
+    pub local_index: Option<PathBuf>,
+
+    /// The `ipb_member_id` cookie, for searching `ExHentai`.
+    #[cfg(feature = "ehentai")]
+    #[arg(long, env = "EHENTAI_MEMBER_ID", hide_env_values = true)]
+    pub ehentai_member_id: Option<String>,
+
+    /// The `ipb_pass_hash` cookie, for searching `ExHentai`.
+    #[cfg(feature = "ehentai")]
+    #[arg(long, env = "EHENTAI_PASS_HASH", hide_env_values = true)]
+    pub ehentai_pass_hash: Option<String>,
+
+    /// The `igneous` cookie, for searching `ExHentai`, if the account has one.
+    #[cfg(feature = "ehentai")]
+    #[arg(long, env = "EHENTAI_IGNEOUS", hide_env_values = true)]
+    pub ehentai_igneous: Option<String>,
+}
+
+impl Keys {
+    /// Fills in anything missing from `self` with what's in `fallback`.
+    ///
+    /// Every field depends on a feature, so with few features enabled there may be nothing to fill in.
+    #[allow(
+        unused_variables,
+        clippy::unused_self,
+        clippy::missing_const_for_fn,
+        clippy::needless_pass_by_value
+    )]
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            #[cfg(feature = "saucenao")]
+            saucenao_api_key: self.saucenao_api_key.or(fallback.saucenao_api_key),
+            #[cfg(feature = "fuzzysearch")]
+            fuzzysearch_api_key: self.fuzzysearch_api_key.or(fallback.fuzzysearch_api_key),
+            #[cfg(feature = "tracemoe")]
+            tracemoe_api_key: self.tracemoe_api_key.or(fallback.tracemoe_api_key),
+            #[cfg(feature = "iqdb_server")]
+            iqdb_server_url: self.iqdb_server_url.or(fallback.iqdb_server_url),
+            #[cfg(feature = "local")]
+            local_index: self.local_index.or(fallback.local_index),
+            #[cfg(feature = "ehentai")]
+            ehentai_member_id: self.ehentai_member_id.or(fallback.ehentai_member_id),
+            #[cfg(feature = "ehentai")]
+            ehentai_pass_hash: self.ehentai_pass_hash.or(fallback.ehentai_pass_hash),
+            #[cfg(feature = "ehentai")]
+            ehentai_igneous: self.ehentai_igneous.or(fallback.ehentai_igneous),
+        }
+    }
+
+    /// The setting called `name`, as asked for by [`source_by_name`](sauce_api::aggregate::source_by_name).
+    ///
+    /// Every field depends on a feature, so with few features enabled there may be nothing to get.
+    #[allow(clippy::unused_self, clippy::missing_const_for_fn)]
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            #[cfg(feature = "saucenao")]
+            "saucenao_api_key" => self.saucenao_api_key.clone(),
+            #[cfg(feature = "fuzzysearch")]
+            "fuzzysearch_api_key" => self.fuzzysearch_api_key.clone(),
+            #[cfg(feature = "tracemoe")]
+            "tracemoe_api_key" => self.tracemoe_api_key.clone(),
+            #[cfg(feature = "iqdb_server")]
+            "iqdb_server_url" => self.iqdb_server_url.clone(),
+            #[cfg(feature = "local")]
+            "local_index" => self
+                .local_index
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned()),
+            #[cfg(feature = "ehentai")]
+            "ehentai_member_id" => self.ehentai_member_id.clone(),
+            #[cfg(feature = "ehentai")]
+            "ehentai_pass_hash" => self.ehentai_pass_hash.clone(),
+            #[cfg(feature = "ehentai")]
+            "ehentai_igneous" => self.ehentai_igneous.clone(),
+            _ => None,
+        }
+    }
+}
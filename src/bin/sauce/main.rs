@@ -0,0 +1,313 @@
+//! `sauce`, for searching for the original source of images from the terminal.
+#![warn(clippy::nursery, clippy::pedantic, clippy::perf)]
+#![deny(unused, clippy::unwrap_used)]
+
+#[cfg(feature = "server")]
+use std::{net::SocketAddr, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::Parser;
+use sauce_api::{
+    aggregate::{self, Aggregator, Checked},
+    input::{DownloadOptions, ImageData, ImageInput},
+    score::{Calibrated, Scorer},
+};
+
+#[cfg(feature = "server")]
+use sauce_api::server::{RateLimit, Server};
+
+use crate::{
+    config::Keys,
+    output::{Format, Printer},
+};
+
+mod config;
+mod output;
+
+#[cfg(not(any(
+    feature = "saucenao",
+    feature = "iqdb",
+    feature = "fuzzysearch",
+    feature = "ascii2d",
+    feature = "bing",
+    feature = "booru",
+    feature = "ehentai",
+    feature = "fluffle",
+    feature = "iqdb_server",
+    feature = "local",
+    feature = "tracemoe",
+    feature = "yandex"
+)))]
+compile_error!("the `sauce` binary needs at least one source feature enabled");
+
+/// Searches for the original source of images.
+#[derive(Debug, Parser)]
+#[command(name = "sauce", version)]
+struct Args {
+    /// Links to images, or paths to local image files.
+    #[cfg_attr(not(feature = "server"), arg(required = true))]
+    #[cfg_attr(feature = "server", arg(required_unless_present = "serve"))]
+    inputs: Vec<String>,
+
+    /// The sources to search, separated by commas.
+    ///
+    /// Defaults to the `sources` in the config file, or else every default source that has the keys it needs.
+    #[arg(short, long, value_delimiter = ',')]
+    sources: Vec<String>,
+
+    /// How to print the results.
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Leaves out items with a lower confidence, from 0 to 1, such as `0.8` for only confident matches.
+    ///
+    /// Each source's similarity is turned into a confidence first, so one threshold fits every source.
+    #[arg(short, long, value_parser = confidence)]
+    min_confidence: Option<f32>,
+
+    /// Downloads each link once and uploads the image to every source, rather than passing the link along.
+    #[arg(short, long)]
+    download_once: bool,
+
+    /// Looks up where image board posts came from, given as `original_source` in JSON output.
+    #[arg(short, long)]
+    resolve_originals: bool,
+
+    /// The config file to read, instead of `$XDG_CONFIG_HOME/sauce/config.toml`.
+    #[arg(short, long, env = "SAUCE_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Serves the sources over HTTP at this address, such as `127.0.0.1:8080`, instead of searching.
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "inputs")]
+    serve: Option<SocketAddr>,
+
+    /// With `--serve`, how many searches to allow in each `--rate-window`, across every client.
+    #[cfg(feature = "server")]
+    #[arg(long, requires = "serve")]
+    rate_limit: Option<u32>,
+
+    /// With `--rate-limit`, how long each window lasts, in seconds.
+    #[cfg(feature = "server")]
+    #[arg(long, default_value_t = 60, requires = "rate_limit")]
+    rate_window: u64,
+
+    /// With `--serve`, also searches links to loopback, private and link-local addresses.
+    ///
+    /// Only for servers that untrusted clients can't reach, as it lets clients make the server fetch from its own network.
+    #[cfg(feature = "server")]
+    #[arg(long, requires = "serve")]
+    allow_private_urls: bool,
+
+    #[command(flatten)]
+    keys: Keys,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(args).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("sauce: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<ExitCode, String> {
+    let file = config::File::load(args.config.as_deref())?;
+    let keys = args.keys.or(file.keys);
+
+    let names = if !args.sources.is_empty() {
+        args.sources
+    } else if !file.sources.is_empty() {
+        file.sources
+    } else {
+        default_sources(&keys)
+    };
+
+    if names.is_empty() {
+        return Err(format!(
+            "no sources to search, pass some with `--sources`. Available: {}",
+            aggregate::source_names().join(", ")
+        ));
+    }
+
+    let mut aggregator = Aggregator::new();
+    for name in &names {
+        let source = aggregate::source_by_name(name, |setting| keys.get(setting))
+            .await
+            .map_err(|e| format!("couldn't set up `{name}`: {e}"))?;
+
+        aggregator = aggregator.with_boxed_source(source);
+    }
+
+    if args.download_once {
+        aggregator = aggregator.download_once(DownloadOptions::default());
+    }
+
+    if args.resolve_originals {
+        aggregator = aggregator.resolve_originals();
+    }
+
+    #[cfg(feature = "server")]
+    if let Some(address) = args.serve {
+        let limit = args.rate_limit.map(|searches| RateLimit {
+            searches,
+            per: Duration::from_secs(args.rate_window),
+        });
+
+        let server = Server::new(aggregator);
+        let server = if args.allow_private_urls {
+            server.allow_private_urls()
+        } else {
+            server
+        };
+
+        return serve(server, address, limit, &names, &keys).await;
+    }
+
+    let scorer = Calibrated::new();
+    let mut printer = Printer::new(args.format);
+    let mut failed = false;
+
+    for input in &args.inputs {
+        let mut results = match search(&aggregator, input).await {
+            Ok(results) => results,
+            Err(e) => {
+                failed = true;
+                printer.failed(input, &e).map_err(|e| e.to_string())?;
+                continue;
+            }
+        };
+
+        if let Some(min) = args.min_confidence {
+            for (source, output) in results
+                .iter_mut()
+                .filter_map(|(source, res)| Some((*source, res.as_mut().ok()?)))
+            {
+                output
+                    .items
+                    .retain(|item| scorer.confidence(source, item) >= min);
+            }
+        }
+
+        failed |= results.iter().all(|(_, res)| res.is_err());
+
+        printer
+            .results(input, &results)
+            .map_err(|e| e.to_string())?;
+    }
+
+    printer.finish().map_err(|e| e.to_string())?;
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Serves the sources until the process is stopped.
+#[cfg(feature = "server")]
+#[allow(unused_variables)]
+async fn serve(
+    mut server: Server,
+    address: SocketAddr,
+    limit: Option<RateLimit>,
+    names: &[String],
+    keys: &Keys,
+) -> Result<ExitCode, String> {
+    if let Some(limit) = limit {
+        server = server.rate_limit(limit);
+    }
+
+    #[cfg(feature = "tracemoe")]
+    if names.iter().any(|name| name == "tracemoe") {
+        use sauce_api::source::{
+            Source,
+            tracemoe::{Config, TraceMoe},
+        };
+
+        let tracemoe = TraceMoe::create(Config {
+            api_key: keys.tracemoe_api_key.clone(),
+            ..Config::default()
+        })
+        .await
+        .map_err(|e| format!("couldn't set up `tracemoe`: {e}"))?;
+
+        server = server.tracemoe_quota(tracemoe);
+    }
+
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|e| format!("couldn't listen on {address}: {e}"))?;
+
+    eprintln!("sauce: serving {} on http://{address}", names.join(", "));
+
+    server.serve(listener).await.map_err(|e| e.to_string())?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Searches a link, or the file at a path.
+async fn search(aggregator: &Aggregator, input: &str) -> Result<Vec<Checked>, String> {
+    let path = Path::new(input);
+
+    if path.is_file() {
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("couldn't read {input}: {e}"))?;
+        let data = ImageData::from_bytes(input, bytes).map_err(|e| e.to_string())?;
+
+        return Ok(aggregator.check_input(&ImageInput::Data(data)).await);
+    }
+
+    if input.starts_with("http://") || input.starts_with("https://") {
+        return aggregator.check(input).await.map_err(|e| e.to_string());
+    }
+
+    Err("not a link, or a path to a file".to_string())
+}
+
+/// Parses `--min-confidence`, which has to be from 0 to 1.
+fn confidence(arg: &str) -> Result<f32, String> {
+    let confidence: f32 = arg
+        .parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())?;
+
+    if (0.0..=1.0).contains(&confidence) {
+        Ok(confidence)
+    } else {
+        Err("must be from 0 to 1, similarities are turned into confidences first".to_string())
+    }
+}
+
+/// The default sources, leaving out those that are missing a key.
+#[allow(
+    unused_mut,
+    unused_variables,
+    clippy::missing_const_for_fn,
+    clippy::vec_init_then_push
+)]
+fn default_sources(keys: &Keys) -> Vec<String> {
+    let mut names = Vec::new();
+
+    #[cfg(feature = "iqdb")]
+    names.push("iqdb".to_string());
+    #[cfg(feature = "saucenao")]
+    if keys.saucenao_api_key.is_some() {
+        names.push("saucenao".to_string());
+    }
+    #[cfg(feature = "fuzzysearch")]
+    if keys.fuzzysearch_api_key.is_some() {
+        names.push("fuzzysearch".to_string());
+    }
+
+    names
+}
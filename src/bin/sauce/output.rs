@@ -0,0 +1,199 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use sauce_api::{
+    aggregate::Checked,
+    score::{Calibrated, Scorer},
+    source::{Output, SourceId},
+};
+use serde::Serialize;
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A table for each input, for reading.
+    Table,
+    /// A single JSON array of records, printed once everything is searched.
+    Json,
+    /// One JSON record per line, printed as each input is searched.
+    Ndjson,
+}
+
+/// The result of searching one input with one source.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    input: &'a str,
+    source: SourceId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<&'a Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// An input that couldn't be searched at all.
+#[derive(Debug, Serialize)]
+struct Failure<'a> {
+    input: &'a str,
+    error: &'a str,
+}
+
+impl<'a> Record<'a> {
+    fn new(input: &'a str, (source, res): &'a Checked) -> Self {
+        match res {
+            Ok(output) => Self {
+                input,
+                source,
+                output: Some(output),
+                error: None,
+            },
+            Err(e) => Self {
+                input,
+                source,
+                output: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Prints results in the chosen [`Format`].
+#[derive(Debug)]
+pub struct Printer {
+    format: Format,
+    /// Everything searched so far, already serialized, for [`Format::Json`].
+    json: Vec<String>,
+    /// Turns each source's similarity into a confidence for [`Format::Table`].
+    scorer: Calibrated,
+}
+
+impl Printer {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            json: Vec::new(),
+            scorer: Calibrated::new(),
+        }
+    }
+
+    /// Prints the results for a single input, or holds on to them for [`Printer::finish`].
+    pub fn results(&mut self, input: &str, results: &[Checked]) -> io::Result<()> {
+        let records = results.iter().map(|checked| Record::new(input, checked));
+
+        match self.format {
+            Format::Table => print_table(input, results, &self.scorer),
+            Format::Json => {
+                for record in records {
+                    self.json.push(serde_json::to_string(&record)?);
+                }
+
+                Ok(())
+            }
+            Format::Ndjson => {
+                let mut out = io::stdout().lock();
+
+                for record in records {
+                    serde_json::to_writer(&mut out, &record)?;
+                    writeln!(out)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Prints an input that couldn't be searched at all.
+    pub fn failed(&mut self, input: &str, error: &str) -> io::Result<()> {
+        let record = Failure { input, error };
+
+        match self.format {
+            Format::Table => {
+                println!("{input}");
+                println!("  error: {error}");
+                println!();
+
+                Ok(())
+            }
+            Format::Json => {
+                self.json.push(serde_json::to_string(&record)?);
+
+                Ok(())
+            }
+            Format::Ndjson => {
+                let mut out = io::stdout().lock();
+                serde_json::to_writer(&mut out, &record)?;
+                writeln!(out)
+            }
+        }
+    }
+
+    /// Prints anything held back until the end.
+    pub fn finish(self) -> io::Result<()> {
+        if self.format == Format::Json {
+            // Joined by hand, as going through `serde_json::Value` would sort the keys
+            let mut out = io::stdout().lock();
+            writeln!(out, "[{}]", self.json.join(",\n"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints a row for each item, with the confidence as a percentage so sources can be compared.
+fn print_table(input: &str, results: &[Checked], scorer: &impl Scorer) -> io::Result<()> {
+    let mut rows = vec![[
+        "SOURCE".to_string(),
+        "CONFIDENCE".to_string(),
+        "SITE".to_string(),
+        "LINK".to_string(),
+    ]];
+
+    let mut errors = Vec::new();
+
+    for (source, res) in results {
+        match res {
+            Ok(output) => rows.extend(output.items.iter().map(|item| {
+                [
+                    (*source).to_string(),
+                    format!("{:.0}%", scorer.confidence(source, item) * 100.0),
+                    item.site.clone().unwrap_or_else(|| "-".to_string()),
+                    item.link.clone(),
+                ]
+            })),
+            Err(e) => errors.push(format!("{source}: {e}")),
+        }
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    writeln!(out, "{input}")?;
+
+    if rows.len() == 1 {
+        writeln!(out, "  no results")?;
+    } else {
+        for row in &rows {
+            writeln!(
+                out,
+                "  {:<w0$}  {:>w1$}  {:<w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )?;
+        }
+    }
+
+    for error in errors {
+        writeln!(out, "  error: {error}")?;
+    }
+
+    writeln!(out)
+}
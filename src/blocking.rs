@@ -0,0 +1,197 @@
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::{
+    aggregate::{Aggregator, Checked},
+    error::Error,
+    input::{ImageData, ImageInput},
+    source::{Output, Source},
+};
+
+/// Wraps a [`Source`] or an [`Aggregator`] with a runtime of its own, so it can be searched without `async`.
+///
+/// Each call blocks the current thread until the search is done.
+/// Calls from within a tokio runtime fail with an [`Error::Generic`] instead, as they would block the runtime.
+///
+/// # Panics
+/// If dropped from within a tokio runtime, as tokio doesn't allow its runtime to be shut down there.
+/// Use [`Blocking::into_inner`] to take the wrapped value back into async code.
+#[derive(Debug)]
+pub struct Blocking<T> {
+    inner: T,
+    runtime: Runtime,
+}
+
+fn runtime() -> Result<Runtime, Error> {
+    Ok(Builder::new_current_thread().enable_all().build()?)
+}
+
+/// Fails if called from within a tokio runtime, where blocking on a search would hold it up.
+fn outside_runtime() -> Result<(), Error> {
+    if Handle::try_current().is_ok() {
+        return Err(Error::Generic(
+            "Blocking can't be used from within an async runtime, search with the wrapped value instead"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+impl<T> Blocking<T> {
+    /// Wraps something that's already been created, such as an [`Aggregator`].
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the runtime couldn't be started
+    pub fn new(inner: T) -> Result<Self, Error> {
+        Ok(Self {
+            inner,
+            runtime: runtime()?,
+        })
+    }
+
+    /// The wrapped source or [`Aggregator`].
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Unwraps the source or [`Aggregator`], for use from async code.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Runs `future` to completion on the runtime, unless called from within another.
+    fn block_on<F: Future>(&self, future: F) -> Result<F::Output, Error> {
+        outside_runtime()?;
+
+        Ok(self.runtime.block_on(future))
+    }
+}
+
+impl<S: Source + Sync> Blocking<S> {
+    /// Creates the source with [`Source::create`], and wraps it.
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the runtime couldn't be started, or if called from within an async runtime
+    /// - Any error from [`Source::create`]
+    pub fn create(state: S::State) -> Result<Self, Error> {
+        outside_runtime()?;
+
+        let runtime = runtime()?;
+        let inner = runtime.block_on(S::create(state))?;
+
+        Ok(Self { inner, runtime })
+    }
+
+    /// Calls [`Source::check`].
+    ///
+    /// # Errors
+    /// Any error from [`Source::check`], or [`Error::Generic`] if called from within an async runtime.
+    pub fn check(&self, url: &str) -> Result<Output, Error> {
+        self.block_on(self.inner.check(url))?
+    }
+
+    /// Calls [`Source::check_data`].
+    ///
+    /// # Errors
+    /// Any error from [`Source::check_data`], or [`Error::Generic`] if called from within an async runtime.
+    pub fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        self.block_on(self.inner.check_data(data))?
+    }
+
+    /// Calls [`Source::check_input`].
+    ///
+    /// # Errors
+    /// Any error from [`Source::check_input`], or [`Error::Generic`] if called from within an async runtime.
+    pub fn check_input(&self, input: &ImageInput) -> Result<Output, Error> {
+        self.block_on(self.inner.check_input(input))?
+    }
+}
+
+impl Blocking<Aggregator> {
+    /// Calls [`Aggregator::check`].
+    ///
+    /// # Errors
+    /// Only if the image had to be downloaded and that failed, see [`Aggregator::prepare`],
+    /// or [`Error::Generic`] if called from within an async runtime.
+    pub fn check(&self, url: &str) -> Result<Vec<Checked>, Error> {
+        self.block_on(self.inner.check(url))?
+    }
+
+    /// Calls [`Aggregator::check_input`].
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if called from within an async runtime
+    pub fn check_input(&self, input: &ImageInput) -> Result<Vec<Checked>, Error> {
+        self.block_on(self.inner.check_input(input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::Blocking;
+    use crate::{
+        aggregate::{Aggregator, AnySource},
+        error::Error,
+        input::{ImageInput, UploadLimits},
+        source::{Output, SourceId},
+    };
+
+    /// A source that finds nothing, without making any requests.
+    #[derive(Debug)]
+    struct Stub;
+
+    #[async_trait]
+    impl AnySource for Stub {
+        fn id(&self) -> SourceId {
+            "stub"
+        }
+
+        fn upload_limits(&self) -> UploadLimits {
+            UploadLimits::DEFAULT
+        }
+
+        async fn search(&self, input: &ImageInput) -> Result<Output, Error> {
+            Ok(Output {
+                original_url: input.origin().to_string(),
+                items: Vec::new(),
+                crop: None,
+            })
+        }
+    }
+
+    fn aggregator() -> Blocking<Aggregator> {
+        Blocking::new(Aggregator::new().with_source(Stub)).expect("the runtime should start")
+    }
+
+    #[test]
+    fn searches_without_async() {
+        let blocking = aggregator();
+
+        let results = blocking
+            .check_input(&"https://example.com/a.png".into())
+            .expect("searching outside a runtime should work");
+
+        assert_eq!(results.len(), 1);
+        let (source, res) = &results[0];
+        assert_eq!(*source, "stub");
+        assert_eq!(
+            res.as_ref().expect("the stub should answer").original_url,
+            "https://example.com/a.png"
+        );
+    }
+
+    #[test]
+    fn fails_from_within_a_runtime() {
+        let blocking = aggregator();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("the runtime should start");
+
+        let res =
+            runtime.block_on(async { blocking.check_input(&"https://example.com/a.png".into()) });
+
+        assert!(matches!(res, Err(Error::Generic(_))), "{res:?}");
+    }
+}
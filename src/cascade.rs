@@ -0,0 +1,191 @@
+use crate::{
+    aggregate::{self, Aggregator, AnySource, Checked, Merged},
+    error::Error,
+    input::{DownloadOptions, ImageInput},
+    score::{Calibrated, Scorer},
+    source::{Item, SourceId},
+};
+
+/// Searches tiers of sources one after another, stopping once a tier finds a confident match.
+///
+/// Meant for putting cheap or unlimited sources in front of ones with a tight quota,
+/// so the quota is only spent when the earlier tiers come back empty or unsure.
+/// The sources within a tier are searched concurrently, as an [`Aggregator`].
+///
+/// Thresholds are confidences from 0 to 1, as given by the [`Scorer`], so they mean the same
+/// whatever scale a source reports its similarity on. The default scorer is [`Calibrated`].
+#[derive(Debug, Default)]
+pub struct Cascade<S = Calibrated> {
+    tiers: Vec<Tier>,
+    /// Holds no sources, only the settings for [`Aggregator::prepare`], shared by every tier.
+    input: Aggregator,
+    scorer: S,
+}
+
+#[derive(Debug)]
+struct Tier {
+    aggregator: Aggregator,
+    threshold: f32,
+}
+
+/// What a [`Cascade`] found, and how far it had to go.
+#[derive(Debug)]
+pub struct Report {
+    /// The results of each tier that was searched, in order. Tiers after a confident match are left out.
+    pub tiers: Vec<Vec<Checked>>,
+    /// The position of the tier that found a confident match, if any did.
+    pub confident: Option<usize>,
+}
+
+impl Report {
+    /// The names of every source that was searched, in tier order.
+    pub fn sources(&self) -> impl Iterator<Item = SourceId> + '_ {
+        self.tiers.iter().flatten().map(|(source, _)| *source)
+    }
+
+    /// The item with the highest confidence across every tier that was searched, and the source that found it.
+    ///
+    /// Uses the [`Calibrated`] scorer, see [`Report::best_with`] for another.
+    #[must_use]
+    pub fn best(&self) -> Option<(SourceId, &Item)> {
+        self.best_with(&Calibrated::new())
+    }
+
+    /// Like [`Report::best`], but with the given [`Scorer`].
+    #[must_use]
+    pub fn best_with(&self, scorer: &impl Scorer) -> Option<(SourceId, &Item)> {
+        self.tiers
+            .iter()
+            .flatten()
+            .filter_map(|(source, res)| Some((*source, res.as_ref().ok()?)))
+            .flat_map(|(source, output)| output.items.iter().map(move |item| (source, item)))
+            .map(|(source, item)| (source, item, scorer.confidence(source, item)))
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+            .map(|(source, item, _)| (source, item))
+    }
+
+    /// The items from every tier that was searched, grouped by work, see [`aggregate::merge`].
+    #[must_use]
+    pub fn merged(&self) -> Vec<Merged> {
+        aggregate::merge(self.tiers.iter().flatten())
+    }
+}
+
+impl Cascade {
+    /// Creates an empty [`Cascade`], scoring with the [`Calibrated`] scorer.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: Scorer + Sync> Cascade<S> {
+    /// Replaces the [`Scorer`] that turns similarities into the confidences compared with each threshold.
+    #[must_use]
+    pub fn scorer<T: Scorer + Sync>(self, scorer: T) -> Cascade<T> {
+        Cascade {
+            tiers: self.tiers,
+            input: self.input,
+            scorer,
+        }
+    }
+
+    /// Adds a tier with a single source.
+    ///
+    /// The cascade stops after this tier if it finds an item with a confidence of at least `threshold`.
+    ///
+    /// # Panics
+    /// If `threshold` isn't between 0 and 1, such as a similarity percentage passed by mistake.
+    #[must_use]
+    pub fn with_source(self, source: impl AnySource + 'static, threshold: f32) -> Self {
+        self.with_tier(Aggregator::new().with_source(source), threshold)
+    }
+
+    /// Adds a tier that searches every source in `aggregator` at once.
+    ///
+    /// The cascade stops after this tier if any of them finds an item with a confidence of at least `threshold`,
+    /// such as [`Confidence::High`](crate::score::Confidence::High)'s `0.8`. A threshold of 0 always stops.
+    /// Settings like [`Aggregator::preprocess`] apply within the tier, but [`Aggregator::download_once`]
+    /// is ignored in favour of [`Cascade::download_once`].
+    ///
+    /// # Panics
+    /// If `threshold` isn't between 0 and 1, such as a similarity percentage passed by mistake.
+    #[must_use]
+    pub fn with_tier(mut self, aggregator: Aggregator, threshold: f32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&threshold),
+            "cascade thresholds are confidences from 0 to 1, got {threshold}"
+        );
+
+        self.tiers.push(Tier {
+            aggregator,
+            threshold,
+        });
+        self
+    }
+
+    /// Downloads the image once, within the given limits, and uploads the bytes to every tier.
+    #[must_use]
+    pub fn download_once(mut self, options: DownloadOptions) -> Self {
+        self.input = self.input.download_once(options);
+        self
+    }
+
+    /// Searches the tiers for the given URL.
+    ///
+    /// # Errors
+    /// Only if the image had to be downloaded and that failed, see [`Cascade::download_once`].
+    /// Errors from individual sources are returned in the [`Report`].
+    pub async fn check(&self, url: &str) -> Result<Report, Error> {
+        let input = self.input.prepare(url).await?;
+
+        Ok(self.check_input(&input).await)
+    }
+
+    /// Searches the tiers in order with the given input, until one finds a confident match.
+    pub async fn check_input(&self, input: &ImageInput) -> Report {
+        let mut report = Report {
+            tiers: Vec::with_capacity(self.tiers.len()),
+            confident: None,
+        };
+
+        for (position, tier) in self.tiers.iter().enumerate() {
+            let results = tier.aggregator.check_input(input).await;
+
+            let confident = results
+                .iter()
+                .filter_map(|(source, res)| Some((*source, res.as_ref().ok()?)))
+                .flat_map(|(source, output)| output.items.iter().map(move |item| (source, item)))
+                .any(|(source, item)| self.scorer.confidence(source, item) >= tier.threshold);
+
+            report.tiers.push(results);
+
+            if confident {
+                report.confident = Some(position);
+                break;
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cascade;
+    use crate::aggregate::Aggregator;
+
+    #[test]
+    fn accepts_thresholds_between_0_and_1() {
+        let _ = Cascade::new()
+            .with_tier(Aggregator::new(), 0.0)
+            .with_tier(Aggregator::new(), 0.8)
+            .with_tier(Aggregator::new(), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "confidences from 0 to 1")]
+    fn rejects_percentages() {
+        let _ = Cascade::new().with_tier(Aggregator::new(), 90.0);
+    }
+}
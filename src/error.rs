@@ -1,12 +1,26 @@
 use std::fmt;
 
+use crate::validate::ImageFormat;
+
 /// Errors for sauce-api
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
-    /// The provided link does not lead to an image file, or the Content-Type is unspecified.
+    /// The provided link does not lead to an image file, or the image format could not be recognised.
     LinkIsNotImage,
 
+    /// The provided link leads to an image, but its format is not allowed.
+    UnsupportedFormat(ImageFormat),
+
+    /// The image is larger than the configured limit, in bytes.
+    TooLarge {
+        /// The limit that was exceeded.
+        limit: u64,
+    },
+
+    /// The source can only search by URL, and does not accept uploaded images.
+    UploadUnsupported,
+
     /// A generic error, aka something in the pipeline went wrong
     Generic(String),
 }
@@ -16,8 +30,15 @@ impl fmt::Display for Error {
         match self {
             Self::LinkIsNotImage => write!(
                 f,
-                "The provided link does not lead to an image file, or the Content-Type is unspecified."
+                "The provided link does not lead to an image file, or the image format could not be recognised."
             ),
+            Self::UnsupportedFormat(format) => {
+                write!(f, "The image format `{format}` is not allowed.")
+            }
+            Self::TooLarge { limit } => {
+                write!(f, "The image is larger than the limit of {limit} bytes.")
+            }
+            Self::UploadUnsupported => write!(f, "The source does not accept uploaded images."),
             Self::Generic(s) => write!(f, "{s}"),
         }
     }
@@ -36,5 +57,6 @@ macro_rules! impl_from {
 impl_from!(reqwest::Error);
 impl_from!(reqwest::header::ToStrError);
 impl_from!(serde_json::Error);
+impl_from!(std::io::Error);
 impl_from!(std::num::ParseFloatError);
 impl_from!(Box<dyn std::error::Error + std::marker::Send + std::marker::Sync>);
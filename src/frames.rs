@@ -0,0 +1,376 @@
+use std::io::Cursor;
+
+use image::{
+    AnimationDecoder, DynamicImage, Frame, ImageDecoder, ImageResult,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    input::ImageData,
+    source::{Output, Source},
+    validate::ImageFormat,
+};
+
+/// The most frames that will be read from an animation.
+const MAX_FRAMES: u64 = 2000;
+
+/// The most pixels a single frame of an animation may have, as each one is decoded in full.
+const MAX_FRAME_PIXELS: u64 = 4096 * 4096;
+
+/// The most pixels that will be decoded across every frame of an animation.
+const MAX_PIXELS: u64 = 256 * 1024 * 1024;
+
+/// Extracts up to `count` representative still frames from an animated GIF, WebP or PNG.
+///
+/// The frames are spread evenly through the animation, taken from the middle of each
+/// stretch so that blank lead-in frames are skipped. Still images are returned as-is.
+///
+/// Frames are decoded one at a time, and only the ones that are picked are kept.
+///
+/// # Errors
+/// - [`Error::Generic`] if the animation can't be decoded, or has more frames or pixels than will be read
+pub fn extract_frames(data: &ImageData, count: usize) -> Result<Vec<ImageData>, Error> {
+    let bytes = data.bytes.as_slice();
+
+    let frames = match data.format {
+        ImageFormat::Gif => {
+            let size = GifDecoder::new(Cursor::new(bytes))
+                .map_err(to_error)?
+                .dimensions();
+
+            collect(|| GifDecoder::new(Cursor::new(bytes)), size, count)
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes)).map_err(to_error)?;
+
+            if !decoder.has_animation() {
+                return Ok(vec![data.clone()]);
+            }
+
+            collect(
+                || WebPDecoder::new(Cursor::new(bytes)),
+                decoder.dimensions(),
+                count,
+            )
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes)).map_err(to_error)?;
+
+            if !decoder.is_apng().map_err(to_error)? {
+                return Ok(vec![data.clone()]);
+            }
+
+            collect(
+                || PngDecoder::new(Cursor::new(bytes))?.apng(),
+                decoder.dimensions(),
+                count,
+            )
+        }
+        _ => return Ok(vec![data.clone()]),
+    }?;
+
+    frames
+        .into_iter()
+        .map(|frame| encode_png(&data.origin, &DynamicImage::ImageRgba8(frame.into_buffer())))
+        .collect()
+}
+
+/// Searches each frame with the given source, one after another, and merges the results.
+///
+/// Frames that fail to be searched are skipped, as long as at least one succeeds.
+///
+/// # Errors
+/// The error from the first frame, if every frame failed.
+pub async fn check_frames<S>(source: &S, frames: &[ImageData]) -> Result<Output, Error>
+where
+    S: Source + Sync,
+{
+    let mut outputs = Vec::with_capacity(frames.len());
+    let mut first_error = None;
+
+    for frame in frames {
+        match source.check_data(frame).await {
+            Ok(output) => outputs.push(output),
+            Err(e) => {
+                warn!(?e, "Failed to search frame");
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    if outputs.is_empty()
+        && let Some(e) = first_error
+    {
+        return Err(e);
+    }
+
+    let original_url = frames.first().map(|f| f.origin.clone()).unwrap_or_default();
+
+    Ok(Output::merge(original_url, outputs))
+}
+
+/// The indices of `count` frames out of `total`, each from the middle of an even stretch.
+fn pick(total: usize, count: usize) -> impl Iterator<Item = usize> {
+    let count = count.clamp(1, total.max(1));
+
+    (0..count)
+        .map(move |i| (2 * i + 1) * total / (2 * count))
+        .filter(move |&i| i < total)
+}
+
+/// Decodes the frames `pick` chooses out of `count`, with `decoder` making a fresh decoder for each pass.
+///
+/// The first pass counts the frames, holding one at a time, and stops at the limits.
+/// The second keeps only the frames that are picked.
+fn collect<'a, D: AnimationDecoder<'a>>(
+    decoder: impl Fn() -> ImageResult<D>,
+    (width, height): (u32, u32),
+    count: usize,
+) -> Result<Vec<Frame>, Error> {
+    let frame_pixels = u64::from(width) * u64::from(height);
+
+    if frame_pixels > MAX_FRAME_PIXELS {
+        return Err(Error::Generic(format!(
+            "Animation frames are too large at {width}x{height}"
+        )));
+    }
+
+    let mut total = 0u64;
+    for frame in decoder().map_err(to_error)?.into_frames() {
+        frame.map_err(to_error)?;
+        total += 1;
+
+        if total > MAX_FRAMES || total * frame_pixels > MAX_PIXELS {
+            return Err(Error::Generic(format!(
+                "Animation is too long, stopped reading after {total} frames"
+            )));
+        }
+    }
+
+    // Bounded by `MAX_FRAMES` above
+    #[allow(clippy::cast_possible_truncation)]
+    let total = total as usize;
+
+    debug!(total, count, "Counted animation frames");
+
+    let mut picked = pick(total, count).peekable();
+    let mut kept = Vec::new();
+
+    // Stops once the last picked frame is read
+    for (i, frame) in decoder().map_err(to_error)?.into_frames().enumerate() {
+        let Some(&next) = picked.peek() else {
+            break;
+        };
+
+        let frame = frame.map_err(to_error)?;
+
+        if i == next {
+            picked.next();
+            kept.push(frame);
+        }
+    }
+
+    Ok(kept)
+}
+
+fn encode_png(origin: &str, image: &DynamicImage) -> Result<ImageData, Error> {
+    let mut bytes = Vec::new();
+
+    image
+        .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .map_err(to_error)?;
+
+    Ok(ImageData {
+        origin: origin.to_string(),
+        bytes,
+        format: ImageFormat::Png,
+    })
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn to_error(e: image::ImageError) -> Error {
+    Error::Generic(e.to_string())
+}
+
+/// Frame extraction for short videos, using the `ffmpeg` and `ffprobe` executables.
+///
+/// Both need to be available on the `PATH`.
+#[cfg(feature = "video")]
+pub mod video {
+    use std::{
+        path::Path,
+        process::{Command, Output as ProcessOutput},
+    };
+
+    use tracing::debug;
+
+    use crate::{
+        error::Error,
+        input::ImageData,
+        validate::{ImageFormat, ftyp_brands},
+    };
+
+    /// Brands of ISO-BMFF files that hold video, as opposed to still images like HEIC and AVIF.
+    const VIDEO_BRANDS: &[&[u8; 4]] = &[
+        b"isom", b"iso2", b"iso3", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"mp71", b"avc1",
+        b"M4V ", b"M4VH", b"M4VP", b"qt  ", b"3gp4", b"3gp5", b"3gp6", b"3g2a", b"dash", b"MSNV",
+        b"mmp4", b"f4v ",
+    ];
+
+    /// Whether the bytes look like an `MP4`, `MOV` or `WebM` video.
+    #[must_use]
+    pub fn is_video(bytes: &[u8]) -> bool {
+        match bytes {
+            // EBML header, used by WebM and Matroska
+            [0x1A, 0x45, 0xDF, 0xA3, ..] => true,
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => {
+                ImageFormat::sniff(bytes).is_none()
+                    && ftyp_brands(bytes).any(|brand| VIDEO_BRANDS.iter().any(|b| b[..] == *brand))
+            }
+            _ => false,
+        }
+    }
+
+    /// Extracts up to `count` still frames, spread evenly through the video at the given path.
+    ///
+    /// This runs `ffmpeg` to completion for each frame, so it blocks the current thread.
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if `ffmpeg` or `ffprobe` can't be run, or fail
+    pub fn extract_frames(path: &Path, count: usize) -> Result<Vec<ImageData>, Error> {
+        let duration = duration(path)?;
+        let count = count.max(1);
+
+        debug!(duration, count, "Extracting video frames");
+
+        (0..count)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let at = duration * (2 * i + 1) as f64 / (2 * count) as f64;
+
+                frame_at(path, at)
+            })
+            .collect()
+    }
+
+    /// Reads the duration of the video, in seconds.
+    fn duration(path: &Path) -> Result<f64, Error> {
+        let out = run(Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "format=duration"])
+            .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+            .arg(path))?;
+
+        Ok(String::from_utf8_lossy(&out.stdout).trim().parse::<f64>()?)
+    }
+
+    /// Grabs a single frame as a PNG.
+    fn frame_at(path: &Path, seconds: f64) -> Result<ImageData, Error> {
+        let out = run(Command::new("ffmpeg")
+            .args(["-v", "error", "-ss", &format!("{seconds:.3}"), "-i"])
+            .arg(path)
+            .args(["-frames:v", "1"])
+            .args(["-f", "image2pipe", "-c:v", "png", "pipe:1"]))?;
+
+        Ok(ImageData {
+            origin: path.display().to_string(),
+            bytes: out.stdout,
+            format: ImageFormat::Png,
+        })
+    }
+
+    fn run(command: &mut Command) -> Result<ProcessOutput, Error> {
+        let program = command.get_program().display().to_string();
+
+        let out = command
+            .output()
+            .map_err(|e| Error::Generic(format!("Failed to run {program}: {e}")))?;
+
+        if !out.status.success() {
+            return Err(Error::Generic(format!(
+                "{program} failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Delay, Frame, Rgba, RgbaImage, codecs::gif::GifEncoder};
+
+    use super::{MAX_FRAMES, extract_frames};
+    use crate::{error::Error, input::ImageData};
+
+    /// A GIF whose frames are each a solid grey, as light as the frame's position.
+    fn gif(frames: u64, size: u32) -> ImageData {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            encoder
+                .encode_frames((0..frames).map(|i| {
+                    let shade = u8::try_from(i % 256).unwrap_or_default();
+                    let image = RgbaImage::from_pixel(size, size, Rgba([shade, shade, shade, 255]));
+                    Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(10, 1))
+                }))
+                .expect("encoding a GIF");
+        }
+
+        ImageData::from_bytes("test.gif", bytes).expect("a GIF")
+    }
+
+    fn shade(frame: &ImageData) -> u8 {
+        image::load_from_memory(&frame.bytes)
+            .expect("a PNG")
+            .to_rgba8()
+            .get_pixel(0, 0)
+            .0[0]
+    }
+
+    #[test]
+    fn picks_frames_from_the_middle_of_each_stretch() {
+        let frames = extract_frames(&gif(9, 4), 3).expect("extracting");
+
+        assert_eq!(frames.iter().map(shade).collect::<Vec<_>>(), [1, 4, 7]);
+    }
+
+    #[test]
+    fn returns_every_frame_of_a_short_animation() {
+        let frames = extract_frames(&gif(2, 4), 5).expect("extracting");
+
+        assert_eq!(frames.iter().map(shade).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn stops_reading_overly_long_animations() {
+        let res = extract_frames(&gif(MAX_FRAMES + 1, 1), 4);
+
+        assert!(matches!(res, Err(Error::Generic(_))), "{res:?}");
+    }
+
+    #[cfg(feature = "video")]
+    #[test]
+    fn tells_videos_from_heic_and_avif() {
+        use super::video::is_video;
+
+        let ftyp = |brands: &[u8]| {
+            let size = u32::try_from(12 + brands.len()).unwrap_or(u32::MAX);
+            let mut bytes = size.to_be_bytes().to_vec();
+            bytes.extend_from_slice(b"ftyp");
+            bytes.extend_from_slice(&brands[..4]);
+            bytes.extend_from_slice(&[0, 0, 0, 0]);
+            bytes.extend_from_slice(&brands[4..]);
+            bytes
+        };
+
+        assert!(is_video(&ftyp(b"isomiso2avc1mp41")));
+        assert!(is_video(&ftyp(b"qt  qt  ")));
+        assert!(is_video(&[0x1A, 0x45, 0xDF, 0xA3, 0x01]));
+        assert!(!is_video(&ftyp(b"heicmif1heic")));
+        assert!(!is_video(&ftyp(b"avifmif1miaf")));
+        assert!(!is_video(b"\x89PNG\r\n\x1a\n"));
+    }
+}
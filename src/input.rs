@@ -0,0 +1,261 @@
+use reqwest::{Client, Response, header};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    error::Error,
+    validate::{ImageFormat, Validator},
+};
+
+/// The default limit on how large a downloaded image may be, 20 MiB.
+pub const DEFAULT_MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+/// What to search for: either a link, or an image that has already been fetched.
+#[derive(Debug, Clone)]
+pub enum ImageInput {
+    /// A link, which each source fetches by itself.
+    Url(String),
+    /// The bytes of an image, which are uploaded to each source.
+    Data(ImageData),
+}
+
+impl ImageInput {
+    /// Where the image came from. Used as [`Output::original_url`](crate::source::Output::original_url).
+    #[must_use]
+    pub fn origin(&self) -> &str {
+        match self {
+            Self::Url(url) => url,
+            Self::Data(data) => &data.origin,
+        }
+    }
+}
+
+impl From<String> for ImageInput {
+    fn from(url: String) -> Self {
+        Self::Url(url)
+    }
+}
+
+impl From<&str> for ImageInput {
+    fn from(url: &str) -> Self {
+        Self::Url(url.to_string())
+    }
+}
+
+impl From<ImageData> for ImageInput {
+    fn from(data: ImageData) -> Self {
+        Self::Data(data)
+    }
+}
+
+/// An image held in memory.
+#[derive(Debug, Clone)]
+pub struct ImageData {
+    /// Where the image came from, usually the URL it was downloaded from.
+    pub origin: String,
+    /// The raw bytes of the image.
+    pub bytes: Vec<u8>,
+    /// The format, as sniffed from the bytes.
+    pub format: ImageFormat,
+}
+
+impl ImageData {
+    /// Wraps bytes that were obtained elsewhere, such as from a local file.
+    ///
+    /// # Errors
+    /// - [`Error::LinkIsNotImage`] if the format couldn't be recognised
+    pub fn from_bytes(origin: impl Into<String>, bytes: Vec<u8>) -> Result<Self, Error> {
+        let format = ImageFormat::sniff(&bytes).ok_or(Error::LinkIsNotImage)?;
+
+        Ok(Self {
+            origin: origin.into(),
+            bytes,
+            format,
+        })
+    }
+
+    /// Downloads an image, enforcing the limits in the given [`DownloadOptions`].
+    ///
+    /// # Errors
+    /// - [`Error::TooLarge`] if the image is larger than [`DownloadOptions::max_size`]
+    /// - [`Error::LinkIsNotImage`] if the format couldn't be recognised
+    /// - [`Error::UnsupportedFormat`] if the format isn't allowed by the [`Validator`]
+    /// - [`Error::Generic`] if the download fails
+    pub async fn download(
+        client: &Client,
+        url: &str,
+        options: &DownloadOptions,
+    ) -> Result<Self, Error> {
+        let resp = client.get(url).send().await?;
+
+        Self::from_response(url, resp, options).await
+    }
+
+    /// Reads a downloaded image from `resp`, enforcing the limits in the given [`DownloadOptions`].
+    pub(crate) async fn from_response(
+        url: &str,
+        resp: Response,
+        options: &DownloadOptions,
+    ) -> Result<Self, Error> {
+        let resp = resp.error_for_status()?;
+
+        let too_large = Error::TooLarge {
+            limit: options.max_size,
+        };
+
+        // Bail out early if the server tells us the size up front
+        let length = resp
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+
+        if length.is_some_and(|l| l > options.max_size) {
+            return Err(too_large);
+        }
+
+        let bytes = read_body(resp, options.max_size.saturating_add(1)).await?;
+        if bytes.len() as u64 > options.max_size {
+            return Err(too_large);
+        }
+
+        debug!(len = bytes.len(), "Downloaded image");
+
+        let data = Self::from_bytes(url, bytes)?;
+
+        if !options.validator.allows(data.format) {
+            return Err(Error::UnsupportedFormat(data.format));
+        }
+
+        Ok(data)
+    }
+
+    /// A file name to use when uploading the image.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        format!("image.{}", self.format.extension())
+    }
+
+    /// Turns the image into a multipart [`Part`](reqwest::multipart::Part), with a file name and MIME type.
+    ///
+    /// # Errors
+    /// Only if the MIME type is rejected by `reqwest`, which shouldn't happen.
+    pub fn to_part(&self) -> Result<reqwest::multipart::Part, Error> {
+        Ok(reqwest::multipart::Part::bytes(self.bytes.clone())
+            .file_name(self.file_name())
+            .mime_str(self.format.mime())?)
+    }
+}
+
+/// Reads the body of `resp` until at least `at_least` bytes are in, or it ends.
+///
+/// In the browser the body can't be read a chunk at a time, so all of it is read.
+pub(crate) async fn read_body(resp: Response, at_least: u64) -> Result<Vec<u8>, Error> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut resp = resp;
+        let mut bytes = Vec::new();
+
+        while (bytes.len() as u64) < at_least {
+            let Some(chunk) = resp.chunk().await? else {
+                break;
+            };
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(bytes)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = at_least;
+
+        Ok(resp.bytes().await?.to_vec())
+    }
+}
+
+/// Limits that apply when an image is downloaded by the crate, rather than by each source.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    max_size: u64,
+    validator: Validator,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_MAX_SIZE,
+            validator: Validator::default(),
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Sets the largest image, in bytes, that will be downloaded.
+    #[must_use]
+    pub const fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets the [`Validator`] that decides which formats are accepted.
+    #[must_use]
+    pub fn validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+}
+
+/// What a source accepts for uploaded images.
+///
+/// Used by the `preprocess` feature to shrink and convert images before they're uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadLimits {
+    /// The largest file, in bytes.
+    pub max_bytes: u64,
+    /// The widest image, in pixels.
+    pub max_width: u32,
+    /// The tallest image, in pixels.
+    pub max_height: u32,
+    /// The formats that can be uploaded.
+    pub formats: &'static [ImageFormat],
+}
+
+impl UploadLimits {
+    /// Conservative limits that most sources are happy with.
+    pub const DEFAULT: Self = Self {
+        max_bytes: 8 * 1024 * 1024,
+        max_width: 4096,
+        max_height: 4096,
+        formats: &[ImageFormat::Jpeg, ImageFormat::Png],
+    };
+
+    /// Whether an image of the given format, size and dimensions can be uploaded as-is.
+    #[must_use]
+    pub fn allows(&self, format: ImageFormat, bytes: u64, width: u32, height: u32) -> bool {
+        self.formats.contains(&format)
+            && bytes <= self.max_bytes
+            && width <= self.max_width
+            && height <= self.max_height
+    }
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A rectangle within an image, in pixels from the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Crop {
+    /// Distance from the left edge.
+    pub x: u32,
+    /// Distance from the top edge.
+    pub y: u32,
+    /// Width of the rectangle.
+    pub width: u32,
+    /// Height of the rectangle.
+    pub height: u32,
+}
@@ -7,18 +7,72 @@
     clippy::unwrap_used
 )]
 #![doc = include_str!("../README.md")]
+// Requests in the browser can't be sent between threads, so neither can searches
+#![cfg_attr(target_arch = "wasm32", allow(clippy::future_not_send))]
 
-use reqwest::Client;
+use reqwest::{Client, ClientBuilder};
+
+/// Contains the [`Aggregator`](aggregate::Aggregator), for searching several sources at once
+pub mod aggregate;
+
+#[cfg(feature = "batch")]
+/// Contains the [`Batch`](batch::Batch), for searching many inputs a few at a time
+pub mod batch;
+
+#[cfg(feature = "blocking")]
+/// Contains [`Blocking`](blocking::Blocking), for searching without `async`
+pub mod blocking;
+
+/// Contains the [`Cascade`](cascade::Cascade), for searching sources one tier at a time
+pub mod cascade;
 
 /// Contains the error type
 pub mod error;
 
+#[cfg(feature = "frames")]
+/// Contains frame extraction, for searching animated images and videos
+pub mod frames;
+
+/// Contains the types for passing an image to a source
+pub mod input;
+
+/// Contains [`SiteRef`](link::SiteRef), for telling which site and post a link points to
+pub mod link;
+
+#[cfg(feature = "preprocess")]
+/// Contains the [`Preprocessor`](preprocess::Preprocessor), for fitting images within upload limits
+pub mod preprocess;
+
+/// Contains the lookup of where image board posts came from
+pub mod resolve;
+
+/// Contains the [`Scorer`](score::Scorer), for comparing similarities across sources
+pub mod score;
+
+#[cfg(feature = "server")]
+/// Contains the [`Server`](server::Server), for searching over HTTP
+pub mod server;
+
 /// Contains the various sources
 pub mod source;
 
+/// Contains the shared check for whether a link leads to an image
+pub mod validate;
+
+#[cfg(feature = "wasm")]
+/// Contains the `wasm-bindgen` wrapper, for searching from the browser
+pub mod wasm;
+
 pub(crate) fn make_client() -> Client {
-    Client::builder()
-        .user_agent(format!("lyssieth/sauce-api v{}", env!("CARGO_PKG_VERSION")))
-        .build()
-        .expect("failed to build client")
+    client_builder().build().expect("failed to build client")
+}
+
+pub(crate) fn client_builder() -> ClientBuilder {
+    let builder = Client::builder();
+
+    // Browsers set the user agent themselves
+    #[cfg(not(target_arch = "wasm32"))]
+    let builder = builder.user_agent(format!("lyssieth/sauce-api v{}", env!("CARGO_PKG_VERSION")));
+
+    builder
 }
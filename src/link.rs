@@ -0,0 +1,518 @@
+use reqwest::Url;
+use serde::Serialize;
+
+/// The first value of `key` in the query string.
+fn query(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// Picks the link to the artist's own post from the sources listed on a repost, such as a booru post.
+///
+/// Links to recognised sites other than image boards win, and are made canonical with [`SiteRef::url`].
+/// Otherwise the first web link is used, as is.
+pub fn original_source<S: AsRef<str>>(sources: impl IntoIterator<Item = S>) -> Option<String> {
+    let mut fallback = None;
+
+    for source in sources {
+        let source = source.as_ref().trim();
+
+        if !(source.starts_with("https://") || source.starts_with("http://")) {
+            continue;
+        }
+
+        match SiteRef::parse(source) {
+            Some(site_ref) if !site_ref.is_board() => return Some(site_ref.url()),
+            _ => fallback = fallback.or_else(|| Some(source.to_string())),
+        }
+    }
+
+    fallback
+}
+
+/// A post or work on a site that's recognised, parsed from a link with [`SiteRef::parse`].
+///
+/// The same work can be linked in many ways, such as through a CDN, an old URL scheme, or a mirror.
+/// Each of them parses to the same [`SiteRef`], which can render a single canonical link with [`SiteRef::url`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "site", rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum SiteRef {
+    /// An illustration on pixiv, including links to `i.pximg.net`.
+    Pixiv {
+        /// The ID of the illustration.
+        illust_id: u64,
+    },
+    /// A post on Twitter or X, including links through mirrors like `fxtwitter.com`.
+    Twitter {
+        /// The handle of the author, if the link has it.
+        user: Option<String>,
+        /// The ID of the post.
+        status_id: u64,
+    },
+    /// A post on Danbooru, or its Safebooru mirror.
+    Danbooru {
+        /// The ID of the post.
+        post_id: u64,
+    },
+    /// A post on Gelbooru.
+    Gelbooru {
+        /// The ID of the post.
+        post_id: u64,
+    },
+    /// A post on yande.re.
+    Yandere {
+        /// The ID of the post.
+        post_id: u64,
+    },
+    /// A post on Konachan.
+    Konachan {
+        /// The ID of the post.
+        post_id: u64,
+    },
+    /// A post on e621, or its e926 mirror.
+    E621 {
+        /// The ID of the post.
+        post_id: u64,
+    },
+    /// A deviation on `DeviantArt`.
+    DeviantArt {
+        /// The ID of the deviation.
+        deviation_id: u64,
+    },
+    /// A project on `ArtStation`.
+    ArtStation {
+        /// The short ID of the project, such as `nY2Jmb`.
+        project: String,
+    },
+    /// A submission on Fur Affinity.
+    FurAffinity {
+        /// The ID of the submission.
+        submission_id: u64,
+    },
+    /// An illustration on Nijie.
+    Nijie {
+        /// The ID of the illustration.
+        illust_id: u64,
+    },
+    /// An illustration on Nico Nico Seiga.
+    Seiga {
+        /// The ID of the illustration, without the `im` prefix.
+        illust_id: u64,
+    },
+    /// A post on pixiv FANBOX.
+    Fanbox {
+        /// The name of the creator, as in `{creator}.fanbox.cc`.
+        creator: String,
+        /// The ID of the post.
+        post_id: u64,
+    },
+    /// A post on Bluesky.
+    Bluesky {
+        /// The handle or DID of the author.
+        handle: String,
+        /// The record key of the post.
+        post_id: String,
+    },
+    /// A gallery on E-Hentai or `ExHentai`.
+    EHentai {
+        /// The ID of the gallery.
+        gallery_id: u64,
+        /// The token needed to view the gallery.
+        token: String,
+    },
+    /// An anime on `AniList`.
+    AniList {
+        /// The ID of the anime.
+        anime_id: u64,
+    },
+}
+
+impl SiteRef {
+    /// Works out which site and post a link points to.
+    ///
+    /// Protocol-relative links, such as `//danbooru.donmai.us/posts/1`, are treated as `https`.
+    /// Returns [`None`] for sites that aren't recognised, for links that don't point at a single post,
+    /// and for text that isn't a link, such as the titles some sources give in place of one.
+    #[must_use]
+    pub fn parse(link: &str) -> Option<Self> {
+        let url = if link.starts_with("//") {
+            Url::parse(&format!("https:{link}"))
+        } else {
+            Url::parse(link)
+        }
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))?;
+
+        let host = url.host_str()?;
+        let host = host
+            .strip_prefix("www.")
+            .or_else(|| host.strip_prefix("m."))
+            .unwrap_or(host);
+
+        let segments = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        Self::art(host, &segments, &url).or_else(|| Self::board(host, &segments, &url))
+    }
+
+    /// Artwork and social sites.
+    fn art(host: &str, segments: &[&str], url: &Url) -> Option<Self> {
+        match (host, segments) {
+            ("pixiv.net", [.., "artworks", id] | ["i", id]) => Some(Self::Pixiv {
+                illust_id: id.parse().ok()?,
+            }),
+            ("pixiv.net" | "embed.pixiv.net", _) => Some(Self::Pixiv {
+                illust_id: query(url, "illust_id")?.parse().ok()?,
+            }),
+            // Like `/img-original/img/2020/01/01/00/00/00/12345_p0.png`, but not a profile picture
+            ("i.pximg.net", [.., file]) if segments.contains(&"img") => {
+                let (id, page) = file.split_once('_')?;
+
+                let is_page = page.starts_with("ugoira")
+                    || page
+                        .strip_prefix('p')
+                        .is_some_and(|p| p.starts_with(|c: char| c.is_ascii_digit()));
+
+                is_page.then_some(())?;
+
+                Some(Self::Pixiv {
+                    illust_id: id.parse().ok()?,
+                })
+            }
+
+            (
+                "twitter.com" | "x.com" | "mobile.twitter.com" | "mobile.x.com" | "fxtwitter.com"
+                | "vxtwitter.com" | "fixupx.com" | "fixvx.com" | "nitter.net",
+                [user, "status" | "statuses", id, ..],
+            ) => Some(Self::Twitter {
+                user: (*user != "i").then(|| (*user).to_string()),
+                status_id: id.parse().ok()?,
+            }),
+            (
+                "twitter.com" | "x.com" | "mobile.twitter.com" | "mobile.x.com",
+                ["i", "web", "status", id, ..],
+            ) => Some(Self::Twitter {
+                user: None,
+                status_id: id.parse().ok()?,
+            }),
+
+            ("deviantart.com", ["deviation", id]) => Some(Self::DeviantArt {
+                deviation_id: id.parse().ok()?,
+            }),
+            // Like `/user/art/some-title-12345`, or `user.deviantart.com/art/some-title-12345`
+            (host, [.., "art", slug]) if host.ends_with("deviantart.com") => {
+                Some(Self::DeviantArt {
+                    deviation_id: slug.rsplit('-').next()?.parse().ok()?,
+                })
+            }
+
+            ("artstation.com", ["artwork", project]) => Some(Self::ArtStation {
+                project: (*project).to_string(),
+            }),
+            (host, ["projects", project]) if host.ends_with(".artstation.com") => {
+                Some(Self::ArtStation {
+                    project: (*project).to_string(),
+                })
+            }
+
+            ("furaffinity.net", ["view" | "full", id]) => Some(Self::FurAffinity {
+                submission_id: id.parse().ok()?,
+            }),
+
+            ("nijie.info", ["view.php" | "view_popup.php"]) => Some(Self::Nijie {
+                illust_id: query(url, "id")?.parse().ok()?,
+            }),
+
+            ("seiga.nicovideo.jp", ["seiga", id]) | ("nico.ms", [id]) => Some(Self::Seiga {
+                illust_id: id.strip_prefix("im")?.parse().ok()?,
+            }),
+
+            ("fanbox.cc", [creator, "posts", id]) => Some(Self::Fanbox {
+                creator: creator.strip_prefix('@')?.to_string(),
+                post_id: id.parse().ok()?,
+            }),
+            (host, ["posts", id]) if host.ends_with(".fanbox.cc") => Some(Self::Fanbox {
+                creator: host.strip_suffix(".fanbox.cc")?.to_string(),
+                post_id: id.parse().ok()?,
+            }),
+
+            ("bsky.app", ["profile", handle, "post", id]) => Some(Self::Bluesky {
+                handle: (*handle).to_string(),
+                post_id: (*id).to_string(),
+            }),
+
+            _ => None,
+        }
+    }
+
+    /// Boorus, galleries and databases.
+    fn board(host: &str, segments: &[&str], url: &Url) -> Option<Self> {
+        match (host, segments) {
+            (
+                "danbooru.donmai.us" | "safebooru.donmai.us" | "donmai.us",
+                ["posts", id] | ["post", "show", id],
+            ) => Some(Self::Danbooru {
+                post_id: id.parse().ok()?,
+            }),
+            ("gelbooru.com", ["index.php"]) if query(url, "s").as_deref() == Some("view") => {
+                Some(Self::Gelbooru {
+                    post_id: query(url, "id")?.parse().ok()?,
+                })
+            }
+            ("yande.re", ["post", "show", id, ..]) => Some(Self::Yandere {
+                post_id: id.parse().ok()?,
+            }),
+            ("konachan.com" | "konachan.net", ["post", "show", id, ..]) => Some(Self::Konachan {
+                post_id: id.parse().ok()?,
+            }),
+            ("e621.net" | "e926.net", ["posts", id] | ["post", "show", id]) => Some(Self::E621 {
+                post_id: id.parse().ok()?,
+            }),
+
+            ("e-hentai.org" | "exhentai.org", ["g", id, token]) => Some(Self::EHentai {
+                gallery_id: id.parse().ok()?,
+                token: (*token).to_string(),
+            }),
+
+            ("anilist.co", ["anime", id, ..]) => Some(Self::AniList {
+                anime_id: id.parse().ok()?,
+            }),
+
+            _ => None,
+        }
+    }
+
+    /// Whether this is a post on an image board, which usually reposts art from elsewhere.
+    #[must_use]
+    pub const fn is_board(&self) -> bool {
+        matches!(
+            self,
+            Self::Danbooru { .. }
+                | Self::Gelbooru { .. }
+                | Self::Yandere { .. }
+                | Self::Konachan { .. }
+                | Self::E621 { .. }
+        )
+    }
+
+    /// The name of the site, such as `pixiv`. The same as the `site` tag when serialized.
+    #[must_use]
+    pub const fn site(&self) -> &'static str {
+        match self {
+            Self::Pixiv { .. } => "pixiv",
+            Self::Twitter { .. } => "twitter",
+            Self::Danbooru { .. } => "danbooru",
+            Self::Gelbooru { .. } => "gelbooru",
+            Self::Yandere { .. } => "yandere",
+            Self::Konachan { .. } => "konachan",
+            Self::E621 { .. } => "e621",
+            Self::DeviantArt { .. } => "deviantart",
+            Self::ArtStation { .. } => "artstation",
+            Self::FurAffinity { .. } => "furaffinity",
+            Self::Nijie { .. } => "nijie",
+            Self::Seiga { .. } => "seiga",
+            Self::Fanbox { .. } => "fanbox",
+            Self::Bluesky { .. } => "bluesky",
+            Self::EHentai { .. } => "ehentai",
+            Self::AniList { .. } => "anilist",
+        }
+    }
+
+    /// The ID of the post or work on its site.
+    ///
+    /// Together with [`SiteRef::site`], this tells works apart, whichever way they were linked.
+    #[must_use]
+    pub fn id(&self) -> String {
+        match self {
+            Self::Pixiv { illust_id: id }
+            | Self::Nijie { illust_id: id }
+            | Self::Seiga { illust_id: id }
+            | Self::Twitter { status_id: id, .. }
+            | Self::Danbooru { post_id: id }
+            | Self::Gelbooru { post_id: id }
+            | Self::Yandere { post_id: id }
+            | Self::Konachan { post_id: id }
+            | Self::E621 { post_id: id }
+            | Self::Fanbox { post_id: id, .. }
+            | Self::DeviantArt { deviation_id: id }
+            | Self::FurAffinity { submission_id: id }
+            | Self::EHentai { gallery_id: id, .. }
+            | Self::AniList { anime_id: id } => id.to_string(),
+            Self::ArtStation { project: id } | Self::Bluesky { post_id: id, .. } => id.clone(),
+        }
+    }
+
+    /// The canonical link to the post or work.
+    #[must_use]
+    pub fn url(&self) -> String {
+        match self {
+            Self::Pixiv { illust_id } => format!("https://www.pixiv.net/artworks/{illust_id}"),
+            Self::Twitter {
+                user: Some(user),
+                status_id,
+            } => format!("https://x.com/{user}/status/{status_id}"),
+            Self::Twitter {
+                user: None,
+                status_id,
+            } => format!("https://x.com/i/status/{status_id}"),
+            Self::Danbooru { post_id } => format!("https://danbooru.donmai.us/posts/{post_id}"),
+            Self::Gelbooru { post_id } => {
+                format!("https://gelbooru.com/index.php?page=post&s=view&id={post_id}")
+            }
+            Self::Yandere { post_id } => format!("https://yande.re/post/show/{post_id}"),
+            Self::Konachan { post_id } => format!("https://konachan.com/post/show/{post_id}"),
+            Self::E621 { post_id } => format!("https://e621.net/posts/{post_id}"),
+            Self::DeviantArt { deviation_id } => {
+                format!("https://www.deviantart.com/deviation/{deviation_id}")
+            }
+            Self::ArtStation { project } => format!("https://www.artstation.com/artwork/{project}"),
+            Self::FurAffinity { submission_id } => {
+                format!("https://www.furaffinity.net/view/{submission_id}/")
+            }
+            Self::Nijie { illust_id } => format!("https://nijie.info/view.php?id={illust_id}"),
+            Self::Seiga { illust_id } => format!("https://seiga.nicovideo.jp/seiga/im{illust_id}"),
+            Self::Fanbox { creator, post_id } => {
+                format!("https://www.fanbox.cc/@{creator}/posts/{post_id}")
+            }
+            Self::Bluesky { handle, post_id } => {
+                format!("https://bsky.app/profile/{handle}/post/{post_id}")
+            }
+            Self::EHentai { gallery_id, token } => {
+                format!("https://e-hentai.org/g/{gallery_id}/{token}/")
+            }
+            Self::AniList { anime_id } => format!("https://anilist.co/anime/{anime_id}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SiteRef, original_source};
+
+    fn parse(link: &str) -> SiteRef {
+        SiteRef::parse(link).unwrap_or_else(|| panic!("{link} should parse"))
+    }
+
+    #[test]
+    fn parses_pixiv_links() {
+        let illust = SiteRef::Pixiv {
+            illust_id: 12_345_678,
+        };
+
+        for link in [
+            "https://www.pixiv.net/artworks/12345678",
+            "https://www.pixiv.net/en/artworks/12345678",
+            "https://www.pixiv.net/member_illust.php?mode=medium&illust_id=12345678",
+            "https://i.pximg.net/img-original/img/2020/01/01/00/00/00/12345678_p0.png",
+            "https://i.pximg.net/c/600x1200_90/img-master/img/2020/01/01/00/00/00/12345678_p1_master1200.jpg",
+            "https://i.pximg.net/img-zip-ugoira/img/2020/01/01/00/00/00/12345678_ugoira600x600.zip",
+        ] {
+            assert_eq!(parse(link), illust, "{link}");
+        }
+
+        assert_eq!(parse(&illust.url()), illust);
+    }
+
+    #[test]
+    fn parses_twitter_links_and_mirrors() {
+        let post = SiteRef::Twitter {
+            user: Some("artist".to_string()),
+            status_id: 1_234_567_890,
+        };
+
+        for link in [
+            "https://twitter.com/artist/status/1234567890",
+            "https://x.com/artist/status/1234567890/photo/1",
+            "https://mobile.twitter.com/artist/status/1234567890",
+            "https://fxtwitter.com/artist/status/1234567890",
+            "https://vxtwitter.com/artist/status/1234567890",
+        ] {
+            assert_eq!(parse(link), post, "{link}");
+        }
+
+        assert_eq!(
+            parse("https://twitter.com/i/web/status/1234567890"),
+            SiteRef::Twitter {
+                user: None,
+                status_id: 1_234_567_890,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_protocol_relative_links() {
+        assert_eq!(
+            parse("//danbooru.donmai.us/posts/123"),
+            SiteRef::Danbooru { post_id: 123 }
+        );
+    }
+
+    #[test]
+    fn parses_old_and_new_booru_paths() {
+        for link in [
+            "https://danbooru.donmai.us/posts/123",
+            "https://danbooru.donmai.us/post/show/123",
+            "https://danbooru.donmai.us/posts/123?q=tag",
+        ] {
+            assert_eq!(parse(link), SiteRef::Danbooru { post_id: 123 }, "{link}");
+        }
+
+        for link in [
+            "https://e621.net/posts/456",
+            "https://e926.net/post/show/456",
+        ] {
+            assert_eq!(parse(link), SiteRef::E621 { post_id: 456 }, "{link}");
+        }
+
+        assert_eq!(
+            parse("https://yande.re/post/show/789/some-tags"),
+            SiteRef::Yandere { post_id: 789 }
+        );
+    }
+
+    #[test]
+    fn rejects_links_that_arent_to_a_post() {
+        for link in [
+            // Titles and other text some sources give in place of a link
+            "Some Artwork Title",
+            "pixiv.net/artworks/12345678",
+            "danbooru.donmai.us",
+            "",
+            // Recognised sites, but not a single post
+            "https://www.pixiv.net/users/123",
+            "https://twitter.com/artist",
+            "https://danbooru.donmai.us/posts?tags=tag",
+            "https://danbooru.donmai.us/posts/abc",
+            "https://i.pximg.net/user-profile/img/2020/01/01/00/00/00/123_abc.png",
+            // Sites that aren't recognised
+            "https://example.com/posts/123",
+            "ftp://danbooru.donmai.us/posts/123",
+        ] {
+            assert_eq!(SiteRef::parse(link), None, "{link}");
+        }
+    }
+
+    #[test]
+    fn original_source_prefers_the_artists_post() {
+        assert_eq!(
+            original_source([
+                "Some title",
+                "https://danbooru.donmai.us/posts/123",
+                "https://example.com/page",
+                "https://i.pximg.net/img-original/img/2020/01/01/00/00/00/12345678_p0.png",
+            ]),
+            Some("https://www.pixiv.net/artworks/12345678".to_string())
+        );
+
+        assert_eq!(
+            original_source(["", "Some title", "https://example.com/page"]),
+            Some("https://example.com/page".to_string())
+        );
+
+        assert_eq!(original_source(["Some title"]), None);
+    }
+}
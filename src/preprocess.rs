@@ -0,0 +1,848 @@
+use std::io::Cursor;
+
+use image::{
+    DynamicImage, ImageDecoder, ImageError, ImageReader, codecs::jpeg::JpegEncoder,
+    imageops::FilterType, metadata::Orientation,
+};
+use tracing::debug;
+
+use crate::{
+    error::Error,
+    input::{Crop, ImageData, UploadLimits},
+    source::Source,
+    validate::ImageFormat,
+};
+
+/// The lowest JPEG quality that will be tried when shrinking an image to fit.
+const MIN_JPEG_QUALITY: u8 = 50;
+
+/// How many times the image will be scaled down before giving up on fitting it.
+const MAX_SHRINK_ATTEMPTS: usize = 8;
+
+/// How far a channel may stray from the border colour and still count as part of the border.
+const BORDER_TOLERANCE: u8 = 16;
+
+/// A uniform band must be at least this many pixels, or 1/50th of the image, to count as framing.
+const MIN_BAND: u32 = 4;
+
+/// A line of a status or navigation bar may have one in this many pixels differ from its background,
+/// for the clock, text and icons.
+const CHROME_OUTLIERS: usize = 5;
+
+/// Status and navigation bars are no taller than 1/8th of the image.
+const MAX_CHROME: usize = 8;
+
+/// Prepares images for uploading, so they fit within a source's [`UploadLimits`].
+///
+/// Images that are too large are downscaled, formats the source doesn't accept are
+/// converted to JPEG or PNG, and metadata such as EXIF is stripped.
+/// Images with an EXIF orientation are turned upright first, so phone photos aren't searched sideways.
+///
+/// Optionally, uniform borders and screenshot framing can be cropped off first, see [`Preprocessor::autocrop`].
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    strip_metadata: bool,
+    jpeg_quality: u8,
+    autocrop: bool,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self {
+            strip_metadata: true,
+            jpeg_quality: 90,
+            autocrop: false,
+        }
+    }
+}
+
+impl Preprocessor {
+    /// Creates a [`Preprocessor`] with the default settings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to strip metadata (EXIF, text chunks, comments) from images. Defaults to `true`.
+    #[must_use]
+    pub const fn strip_metadata(mut self, strip_metadata: bool) -> Self {
+        self.strip_metadata = strip_metadata;
+        self
+    }
+
+    /// The JPEG quality to start at when re-encoding, from 1 to 100. Defaults to `90`.
+    #[must_use]
+    pub const fn jpeg_quality(mut self, jpeg_quality: u8) -> Self {
+        self.jpeg_quality = jpeg_quality;
+        self
+    }
+
+    /// Whether to crop off uniform borders and screenshot framing before searching. Defaults to `false`.
+    ///
+    /// This looks for bands of solid colour, such as letterboxing or the background around a
+    /// Discord embed, and keeps the largest stretch of the image between them.
+    /// Status and navigation bars are cropped off the top and bottom edges too: lines that are
+    /// mostly one colour, apart from the clock, text and icons, and no taller than an eighth of the image.
+    #[must_use]
+    pub const fn autocrop(mut self, autocrop: bool) -> Self {
+        self.autocrop = autocrop;
+        self
+    }
+
+    /// Crops the image if [`Preprocessor::autocrop`] is enabled and framing was found.
+    ///
+    /// Returns the image to search, along with the rectangle that was kept, if any.
+    /// The rectangle is in the upright image, after any EXIF orientation is applied.
+    ///
+    /// # Errors
+    /// - [`Error::UnsupportedFormat`] if the image can't be decoded, such as AVIF
+    pub fn crop(&self, data: &ImageData) -> Result<(ImageData, Option<Crop>), Error> {
+        if !self.autocrop {
+            return Ok((data.clone(), None));
+        }
+
+        let image = Self::decode(data)?;
+
+        let Some(crop) = find_crop(&image) else {
+            return Ok((data.clone(), None));
+        };
+
+        debug!(?crop, "Cropping off framing");
+
+        let image = image.crop_imm(crop.x, crop.y, crop.width, crop.height);
+        let format = match data.format {
+            ImageFormat::Jpeg => ImageFormat::Jpeg,
+            _ => ImageFormat::Png,
+        };
+
+        let cropped = ImageData {
+            origin: data.origin.clone(),
+            bytes: encode(&image, format, self.jpeg_quality.clamp(1, 100))?,
+            format,
+        };
+
+        Ok((cropped, Some(crop)))
+    }
+
+    /// Prepares an image for uploading to the given [`Source`], using its [`Source::UPLOAD_LIMITS`].
+    ///
+    /// # Errors
+    /// See [`Preprocessor::prepare`].
+    pub fn prepare_for<S: Source>(&self, data: &ImageData) -> Result<ImageData, Error> {
+        self.prepare(data, &S::UPLOAD_LIMITS)
+    }
+
+    /// Prepares an image for uploading within the given limits.
+    ///
+    /// Images that already fit are left alone, apart from having their metadata stripped.
+    /// If stripping it would lose an EXIF orientation, the image is turned upright and re-encoded instead.
+    /// Images that can't be decoded, such as AVIF, are passed through untouched if their format and size are allowed.
+    ///
+    /// # Errors
+    /// - [`Error::UnsupportedFormat`] if the image needs converting but can't be decoded
+    /// - [`Error::TooLarge`] if the image can't be shrunk to fit
+    pub fn prepare(&self, data: &ImageData, limits: &UploadLimits) -> Result<ImageData, Error> {
+        let mut decoder = match Self::decoder(data) {
+            Ok(decoder) => decoder,
+            Err(Error::UnsupportedFormat(_))
+                if limits.formats.contains(&data.format)
+                    && data.bytes.len() as u64 <= limits.max_bytes =>
+            {
+                debug!(%data.format, "Can't decode the image, uploading it as it is");
+                return Ok(data.clone());
+            }
+            Err(e) => return Err(e),
+        };
+        let (width, height) = decoder.dimensions();
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let upright = orientation == Orientation::NoTransforms;
+
+        if limits.allows(data.format, data.bytes.len() as u64, width, height) {
+            if !self.strip_metadata {
+                return Ok(data.clone());
+            }
+
+            if upright {
+                return Ok(strip_metadata(data));
+            }
+        }
+
+        debug!(%data.format, width, height, ?orientation, "Image needs re-encoding");
+
+        let mut image =
+            DynamicImage::from_decoder(decoder).map_err(|e| Self::map_error(e, data.format))?;
+        image.apply_orientation(orientation);
+
+        let image = if image.width() > limits.max_width || image.height() > limits.max_height {
+            image.resize(limits.max_width, limits.max_height, FilterType::Lanczos3)
+        } else {
+            image
+        };
+
+        let format = Self::target_format(data.format, &image, limits);
+
+        self.encode_within(&data.origin, image, format, limits)
+    }
+
+    /// Picks a format to re-encode into, preferring to keep the original.
+    fn target_format(
+        original: ImageFormat,
+        image: &DynamicImage,
+        limits: &UploadLimits,
+    ) -> ImageFormat {
+        let allowed = |f| limits.formats.contains(&f);
+
+        match original {
+            ImageFormat::Jpeg | ImageFormat::Png if allowed(original) => original,
+            _ if image.color().has_alpha() && allowed(ImageFormat::Png) => ImageFormat::Png,
+            _ if allowed(ImageFormat::Jpeg) => ImageFormat::Jpeg,
+            _ => ImageFormat::Png,
+        }
+    }
+
+    /// Encodes the image, lowering the quality and then the size until it fits in `max_bytes`.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn encode_within(
+        &self,
+        origin: &str,
+        mut image: DynamicImage,
+        mut format: ImageFormat,
+        limits: &UploadLimits,
+    ) -> Result<ImageData, Error> {
+        let mut quality = self.jpeg_quality.clamp(1, 100);
+
+        for _ in 0..MAX_SHRINK_ATTEMPTS {
+            let bytes = encode(&image, format, quality)?;
+
+            if bytes.len() as u64 <= limits.max_bytes {
+                return Ok(ImageData {
+                    origin: origin.to_string(),
+                    bytes,
+                    format,
+                });
+            }
+
+            debug!(len = bytes.len(), %format, quality, "Still too large");
+
+            if format == ImageFormat::Png && limits.formats.contains(&ImageFormat::Jpeg) {
+                format = ImageFormat::Jpeg;
+            } else if format == ImageFormat::Jpeg && quality > MIN_JPEG_QUALITY {
+                quality = quality.saturating_sub(15).max(MIN_JPEG_QUALITY);
+            } else {
+                let width = (image.width() as f32 * 0.75) as u32;
+                let height = (image.height() as f32 * 0.75) as u32;
+
+                image = image.resize(width.max(1), height.max(1), FilterType::Lanczos3);
+            }
+        }
+
+        Err(Error::TooLarge {
+            limit: limits.max_bytes,
+        })
+    }
+
+    /// A decoder for the image, which can read its size and orientation without decoding it.
+    fn decoder(data: &ImageData) -> Result<impl ImageDecoder + '_, Error> {
+        ImageReader::new(Cursor::new(&data.bytes))
+            .with_guessed_format()
+            .map_err(|e| Error::Generic(e.to_string()))?
+            .into_decoder()
+            .map_err(|e| Self::map_error(e, data.format))
+    }
+
+    /// Decodes the image and turns it upright.
+    fn decode(data: &ImageData) -> Result<DynamicImage, Error> {
+        let mut decoder = Self::decoder(data)?;
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+
+        let mut image =
+            DynamicImage::from_decoder(decoder).map_err(|e| Self::map_error(e, data.format))?;
+        image.apply_orientation(orientation);
+
+        Ok(image)
+    }
+
+    fn map_error(error: ImageError, format: ImageFormat) -> Error {
+        match error {
+            ImageError::Unsupported(_) => Error::UnsupportedFormat(format),
+            e => Error::Generic(e.to_string()),
+        }
+    }
+}
+
+/// Finds the largest stretch of the image between bands of uniform colour.
+///
+/// Returns `None` if there's nothing to crop, or if what's left would be implausibly small.
+fn find_crop(image: &DynamicImage) -> Option<Crop> {
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+
+    let rows: Vec<(usize, usize)> = (0..height)
+        .map(|y| outliers((0..width).map(|x| rgb.get_pixel(x, y).0)))
+        .collect();
+
+    let mut uniform: Vec<bool> = rows.iter().map(|&(o, n)| o <= n / 100).collect();
+    let flat: Vec<bool> = rows
+        .iter()
+        .map(|&(o, n)| o <= n / CHROME_OUTLIERS)
+        .collect();
+    mark_chrome(&mut uniform, &flat);
+
+    let (y, h) = content_span(&uniform)?;
+
+    let columns: Vec<bool> = (0..width)
+        .map(|x| is_uniform((y..y + h).map(|y| rgb.get_pixel(x, y).0)))
+        .collect();
+    let (x, w) = content_span(&columns)?;
+
+    let crop = Crop {
+        x,
+        y,
+        width: w,
+        height: h,
+    };
+
+    (crop.width != width || crop.height != height).then_some(crop)
+}
+
+/// Whether a line of pixels is a single colour, ignoring a few stray pixels.
+fn is_uniform(pixels: impl ExactSizeIterator<Item = [u8; 3]>) -> bool {
+    let (outliers, total) = outliers(pixels);
+
+    outliers <= total / 100
+}
+
+/// How many pixels in a line stray from the colour of the first, and how many there are in all.
+fn outliers(mut pixels: impl ExactSizeIterator<Item = [u8; 3]>) -> (usize, usize) {
+    let total = pixels.len();
+
+    let Some(reference) = pixels.next() else {
+        return (0, 0);
+    };
+
+    let outliers = pixels
+        .filter(|p| {
+            p.iter()
+                .zip(reference)
+                .any(|(a, b)| a.abs_diff(b) > BORDER_TOLERANCE)
+        })
+        .count();
+
+    (outliers, total)
+}
+
+/// Marks a status bar along the top edge and a navigation bar along the bottom as framing.
+///
+/// Each is a run of `flat` lines touching the edge, no taller than [`MAX_CHROME`] allows.
+fn mark_chrome(uniform: &mut [bool], flat: &[bool]) {
+    let max = uniform.len() / MAX_CHROME;
+
+    let top = flat.iter().take_while(|&&f| f).count();
+    if top <= max {
+        uniform[..top].fill(true);
+    }
+
+    let bottom = flat.iter().rev().take_while(|&&f| f).count();
+    if bottom <= max {
+        let len = uniform.len();
+        uniform[len - bottom..].fill(true);
+    }
+}
+
+/// Given which lines are uniform, finds the start and length of the longest run of content.
+///
+/// Only uniform bands thick enough to be framing split the content. Returns `None` if the
+/// content would be less than a quarter of the image.
+#[allow(clippy::cast_possible_truncation)]
+fn content_span(uniform: &[bool]) -> Option<(u32, u32)> {
+    let total = uniform.len();
+    let min_band = (total / 50).max(MIN_BAND as usize);
+
+    let mut best = (0, 0);
+    let mut start = None;
+    let mut band = 0;
+
+    for (i, &is_uniform) in uniform.iter().enumerate() {
+        if is_uniform {
+            band += 1;
+
+            // The band is thick enough to be framing, so the content before it has ended
+            if band == min_band
+                && let Some(s) = start.take()
+            {
+                let end = i + 1 - band;
+                if end - s > best.1 - best.0 {
+                    best = (s, end);
+                }
+            }
+        } else {
+            if start.is_none() {
+                start = Some(i);
+            }
+            band = 0;
+        }
+    }
+
+    if let Some(s) = start {
+        // Trailing uniform lines thinner than a band are still trimmed
+        let end = total - band;
+        if end - s > best.1 - best.0 {
+            best = (s, end);
+        }
+    }
+
+    let (s, e) = best;
+
+    (e - s >= total / 4 && e > s).then_some((s as u32, (e - s) as u32))
+}
+
+/// Encodes an image as either JPEG or PNG.
+fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+
+    let res = if format == ImageFormat::Jpeg {
+        // JPEG has no alpha channel, so it has to go
+        let rgb = image.to_rgb8();
+        rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))
+    } else {
+        image.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+    };
+
+    res.map_err(|e| Error::Generic(e.to_string()))?;
+
+    Ok(bytes)
+}
+
+/// Strips metadata from JPEG and PNG files without re-encoding them.
+///
+/// Other formats, and files that can't be walked, are returned unchanged.
+/// So are JPEGs with an EXIF orientation, as stripping it would leave them sideways or mirrored,
+/// see [`Preprocessor::prepare`] for turning them upright first.
+#[must_use]
+pub fn strip_metadata(data: &ImageData) -> ImageData {
+    let stripped = match data.format {
+        ImageFormat::Jpeg => strip_jpeg(&data.bytes),
+        ImageFormat::Png => strip_png(&data.bytes),
+        _ => None,
+    };
+
+    ImageData {
+        origin: data.origin.clone(),
+        bytes: stripped.unwrap_or_else(|| data.bytes.clone()),
+        format: data.format,
+    }
+}
+
+/// Drops the `APP1`..`APP15` and `COM` segments, which hold EXIF, XMP and comments.
+///
+/// `APP14` is kept, as Adobe's segment says how CMYK and YCCK files store their colours,
+/// and so are the `APP2` segments holding the ICC profile, which wide-gamut images need to look right.
+/// Returns `None` if the EXIF has an orientation other than upright, which would be lost.
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(bytes.get(..2)?);
+
+    let mut pos = 2;
+    loop {
+        if *bytes.get(pos)? != 0xFF {
+            return None;
+        }
+
+        let marker = *bytes.get(pos + 1)?;
+
+        // Padding between segments
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+
+        // Start of scan, the entropy-coded data follows with no length to go by
+        if marker == 0xDA {
+            out.extend_from_slice(bytes.get(pos..)?);
+            return Some(out);
+        }
+
+        let len = usize::from(u16::from_be_bytes([
+            *bytes.get(pos + 2)?,
+            *bytes.get(pos + 3)?,
+        ]));
+        let segment = bytes.get(pos..pos + 2 + len)?;
+
+        if marker == 0xE1
+            && let Some(exif) = segment.get(4..).and_then(|s| s.strip_prefix(b"Exif\0\0"))
+            && Orientation::from_exif_chunk(exif).is_some_and(|o| o != Orientation::NoTransforms)
+        {
+            return None;
+        }
+
+        let icc = marker == 0xE2
+            && segment
+                .get(4..)
+                .is_some_and(|s| s.starts_with(b"ICC_PROFILE\0"));
+
+        if icc || !matches!(marker, 0xE1..=0xED | 0xEF | 0xFE) {
+            out.extend_from_slice(segment);
+        }
+
+        pos += 2 + len;
+    }
+}
+
+/// Drops the text, EXIF and timestamp chunks.
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(bytes.get(..8)?);
+
+    let mut pos = 8;
+    while pos < bytes.len() {
+        let len = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        // Length, type, data and CRC
+        let chunk = bytes.get(pos..pos + 12 + len)?;
+
+        if !matches!(kind, b"tEXt" | b"zTXt" | b"iTXt" | b"eXIf" | b"tIME") {
+            out.extend_from_slice(chunk);
+        }
+
+        pos += 12 + len;
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageReader, RgbImage, codecs::jpeg::JpegEncoder};
+
+    use super::{Preprocessor, content_span, find_crop, mark_chrome, strip_jpeg, strip_metadata};
+    use crate::{
+        error::Error,
+        input::{Crop, ImageData, UploadLimits},
+        validate::ImageFormat,
+    };
+
+    /// An image with enough detail that it doesn't compress to nothing.
+    #[allow(clippy::cast_possible_truncation)]
+    fn noise(width: u32, height: u32) -> RgbImage {
+        let mut state = 0x2545_F491_u32;
+
+        RgbImage::from_fn(width, height, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let [r, g, b, _] = state.to_le_bytes();
+            image::Rgb([r, g, b])
+        })
+    }
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        noise(width, height)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, 90))
+            .expect("encoding a JPEG");
+        bytes
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(noise(width, height))
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .expect("encoding a PNG");
+        bytes
+    }
+
+    /// Inserts a segment right after the start of the image.
+    fn with_segment(jpeg: &[u8], marker: u8, payload: &[u8]) -> Vec<u8> {
+        let len = u16::try_from(payload.len() + 2).expect("a short segment");
+
+        let mut bytes = jpeg[..2].to_vec();
+        bytes.extend_from_slice(&[0xFF, marker]);
+        bytes.extend_from_slice(&len.to_be_bytes());
+        bytes.extend_from_slice(payload);
+        bytes.extend_from_slice(&jpeg[2..]);
+        bytes
+    }
+
+    /// An `APP1` payload holding only an EXIF orientation.
+    fn exif(orientation: u16) -> Vec<u8> {
+        let mut bytes = b"Exif\0\0MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        bytes.extend_from_slice(&orientation.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    fn data(bytes: Vec<u8>) -> ImageData {
+        ImageData::from_bytes("test", bytes).expect("a known format")
+    }
+
+    fn dimensions(bytes: &[u8]) -> (u32, u32) {
+        ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .expect("reading from memory")
+            .into_dimensions()
+            .expect("a readable image")
+    }
+
+    const ADOBE: &[u8] = b"Adobe\0\x64\0\0\0\0\x02";
+
+    #[test]
+    fn strip_jpeg_drops_metadata_but_keeps_adobe() {
+        let base = jpeg(8, 8);
+
+        let mut bytes = with_segment(&base, 0xFE, b"a comment");
+        bytes = with_segment(&bytes, 0xEF, b"APP15");
+        bytes = with_segment(&bytes, 0xED, b"Photoshop 3.0\0");
+        bytes = with_segment(&bytes, 0xE2, b"FPXR\0");
+        bytes = with_segment(&bytes, 0xE1, &exif(1));
+        bytes = with_segment(&bytes, 0xEE, ADOBE);
+
+        assert_eq!(strip_jpeg(&bytes), Some(with_segment(&base, 0xEE, ADOBE)));
+    }
+
+    #[test]
+    fn strip_jpeg_keeps_the_icc_profile() {
+        const ICC: &[u8] = b"ICC_PROFILE\0\x01\x01profile";
+
+        let base = with_segment(&jpeg(8, 8), 0xE2, ICC);
+        let bytes = with_segment(&base, 0xE1, &exif(1));
+
+        assert_eq!(strip_jpeg(&bytes), Some(base));
+    }
+
+    #[test]
+    fn strip_jpeg_keeps_files_with_an_orientation() {
+        let bytes = with_segment(&jpeg(8, 8), 0xE1, &exif(6));
+
+        assert_eq!(strip_jpeg(&bytes), None);
+        assert_eq!(strip_metadata(&data(bytes.clone())).bytes, bytes);
+    }
+
+    #[test]
+    fn strip_jpeg_gives_up_on_broken_files() {
+        let bytes = jpeg(8, 8);
+
+        assert_eq!(strip_jpeg(&[0xFF, 0xD8, 0x00]), None);
+        assert_eq!(strip_jpeg(&bytes[..6]), None);
+    }
+
+    #[test]
+    fn prepare_leaves_images_that_fit() {
+        let bytes = png(16, 16);
+        let prepared = Preprocessor::new()
+            .prepare(&data(bytes), &UploadLimits::DEFAULT)
+            .expect("preparing");
+
+        assert_eq!(prepared.format, ImageFormat::Png);
+        assert_eq!(dimensions(&prepared.bytes), (16, 16));
+    }
+
+    #[test]
+    fn prepare_downscales_keeping_the_aspect_ratio() {
+        let limits = UploadLimits {
+            max_width: 50,
+            max_height: 50,
+            ..UploadLimits::DEFAULT
+        };
+
+        let prepared = Preprocessor::new()
+            .prepare(&data(jpeg(200, 100)), &limits)
+            .expect("preparing");
+
+        assert_eq!(prepared.format, ImageFormat::Jpeg);
+        assert_eq!(dimensions(&prepared.bytes), (50, 25));
+    }
+
+    #[test]
+    fn prepare_converts_formats_that_arent_accepted() {
+        let limits = UploadLimits {
+            formats: &[ImageFormat::Jpeg],
+            ..UploadLimits::DEFAULT
+        };
+
+        let prepared = Preprocessor::new()
+            .prepare(&data(png(16, 16)), &limits)
+            .expect("preparing");
+
+        assert_eq!(prepared.format, ImageFormat::Jpeg);
+        assert_eq!(ImageFormat::sniff(&prepared.bytes), Some(ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn prepare_shrinks_to_fit_the_size_limit() {
+        let limits = UploadLimits {
+            max_bytes: 20_000,
+            ..UploadLimits::DEFAULT
+        };
+
+        let original = png(256, 256);
+        assert!(original.len() > 20_000);
+
+        let prepared = Preprocessor::new()
+            .prepare(&data(original), &limits)
+            .expect("preparing");
+
+        assert!(prepared.bytes.len() <= 20_000, "{}", prepared.bytes.len());
+    }
+
+    #[test]
+    fn prepare_gives_up_when_nothing_fits() {
+        let limits = UploadLimits {
+            max_bytes: 10,
+            ..UploadLimits::DEFAULT
+        };
+
+        let res = Preprocessor::new().prepare(&data(png(64, 64)), &limits);
+
+        assert!(matches!(res, Err(Error::TooLarge { limit: 10 })), "{res:?}");
+    }
+
+    /// The start of an AVIF file, which the `image` crate can't decode.
+    fn avif() -> Vec<u8> {
+        b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf".to_vec()
+    }
+
+    #[test]
+    fn prepare_passes_through_undecodable_images_that_fit() {
+        let limits = UploadLimits {
+            formats: &[ImageFormat::Jpeg, ImageFormat::Avif],
+            ..UploadLimits::DEFAULT
+        };
+
+        let prepared = Preprocessor::new()
+            .prepare(&data(avif()), &limits)
+            .expect("preparing");
+
+        assert_eq!(prepared.format, ImageFormat::Avif);
+        assert_eq!(prepared.bytes, avif());
+    }
+
+    #[test]
+    fn prepare_fails_when_an_undecodable_image_needs_converting() {
+        let res = Preprocessor::new().prepare(&data(avif()), &UploadLimits::DEFAULT);
+
+        assert!(
+            matches!(res, Err(Error::UnsupportedFormat(ImageFormat::Avif))),
+            "{res:?}"
+        );
+    }
+
+    #[test]
+    fn prepare_turns_rotated_photos_upright() {
+        // Orientation 6 is a photo taken with the phone held upright, stored sideways
+        let bytes = with_segment(&jpeg(40, 20), 0xE1, &exif(6));
+
+        let prepared = Preprocessor::new()
+            .prepare(&data(bytes), &UploadLimits::DEFAULT)
+            .expect("preparing");
+
+        assert_eq!(prepared.format, ImageFormat::Jpeg);
+        assert_eq!(dimensions(&prepared.bytes), (20, 40));
+        assert!(strip_jpeg(&prepared.bytes).is_some());
+    }
+
+    /// `lines` of `value`, for building the flags `content_span` takes.
+    fn run(value: bool, lines: usize) -> impl Iterator<Item = bool> {
+        std::iter::repeat_n(value, lines)
+    }
+
+    #[test]
+    fn content_span_finds_letterboxed_content() {
+        let lines: Vec<bool> = run(true, 10)
+            .chain(run(false, 80))
+            .chain(run(true, 10))
+            .collect();
+
+        assert_eq!(content_span(&lines), Some((10, 80)));
+    }
+
+    #[test]
+    fn content_span_keeps_images_without_borders() {
+        assert_eq!(content_span(&[false; 100]), Some((0, 100)));
+    }
+
+    #[test]
+    fn content_span_gives_up_on_uniform_images() {
+        assert_eq!(content_span(&[true; 100]), None);
+    }
+
+    #[test]
+    fn content_span_ignores_thin_bands_inside_the_content() {
+        let lines: Vec<bool> = run(false, 50)
+            .chain(run(true, 2))
+            .chain(run(false, 48))
+            .collect();
+
+        assert_eq!(content_span(&lines), Some((0, 100)));
+    }
+
+    #[test]
+    fn content_span_keeps_the_largest_stretch_between_thick_bands() {
+        let lines: Vec<bool> = run(false, 10)
+            .chain(run(true, 10))
+            .chain(run(false, 70))
+            .chain(run(true, 10))
+            .collect();
+
+        assert_eq!(content_span(&lines), Some((20, 70)));
+    }
+
+    #[test]
+    fn mark_chrome_only_marks_short_runs_at_the_edges() {
+        let flat: Vec<bool> = run(true, 10)
+            .chain(run(false, 40))
+            .chain(run(true, 10))
+            .chain(run(false, 40))
+            .collect();
+        let mut uniform = vec![false; 100];
+
+        mark_chrome(&mut uniform, &flat);
+
+        assert!(uniform[..10].iter().all(|&u| u));
+        assert!(uniform[10..].iter().all(|&u| !u));
+
+        // Too tall to be a status bar, so it's left for the uniform bands to decide
+        let flat: Vec<bool> = run(true, 30).chain(run(false, 70)).collect();
+        let mut uniform = vec![false; 100];
+
+        mark_chrome(&mut uniform, &flat);
+
+        assert!(uniform.iter().all(|&u| !u));
+    }
+
+    #[test]
+    fn find_crop_removes_status_and_navigation_bars() {
+        let content = noise(100, 176);
+
+        let image = RgbImage::from_fn(100, 200, |x, y| match y {
+            // A status bar with the clock on the left
+            0..12 if (4..8).contains(&y) && (4..14).contains(&x) => image::Rgb([0, 0, 0]),
+            // A navigation bar with three buttons
+            188.. if (192..196).contains(&y) && [20, 50, 80].iter().any(|c| x.abs_diff(*c) < 3) => {
+                image::Rgb([0, 0, 0])
+            }
+            0..12 | 188.. => image::Rgb([230, 230, 230]),
+            _ => *content.get_pixel(x, y - 12),
+        });
+
+        assert_eq!(
+            find_crop(&DynamicImage::ImageRgb8(image)),
+            Some(Crop {
+                x: 0,
+                y: 12,
+                width: 100,
+                height: 176,
+            })
+        );
+    }
+
+    #[test]
+    fn find_crop_leaves_photos_alone() {
+        assert_eq!(find_crop(&DynamicImage::ImageRgb8(noise(100, 100))), None);
+    }
+}
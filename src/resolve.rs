@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use futures_util::{StreamExt, lock::Mutex as AsyncMutex, stream};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    link::{self, SiteRef},
+    make_client,
+    source::Item,
+};
+
+/// How many posts are looked up at once, so a page of results doesn't flood the boards.
+const CONCURRENCY: usize = 4;
+
+/// Looks up where each image board post in `items` came from, filling in [`Item::original_source`].
+///
+/// Only items on a board, see [`SiteRef::is_board`], that don't already have an original source are looked up,
+/// by asking the board for the post. Each post is only asked for once, and a few at a time.
+/// Posts that couldn't be fetched, or that don't list a source, are left as they are.
+pub async fn originals(items: &mut [Item]) {
+    Resolver::new(Vec::new()).originals(items).await;
+}
+
+/// The original source of a post, or [`None`] inside if it has none, once it's been looked up.
+type Slot = Arc<AsyncMutex<Option<Option<String>>>>;
+
+/// A login for asking a board about its posts, see [`Source::board_login`](crate::source::Source::board_login).
+#[derive(Debug, Clone)]
+pub struct BoardLogin {
+    /// The [`SiteRef::site`] the login is for.
+    pub site: &'static str,
+    /// The username, or login.
+    pub username: String,
+    /// The API key.
+    pub api_key: String,
+}
+
+/// Looks up the original sources for one search, sharing the lookups between every source's results.
+#[derive(Debug)]
+pub(crate) struct Resolver {
+    client: Client,
+    logins: Vec<BoardLogin>,
+    /// What each post listed, held locked while it's being fetched so other sources wait for it.
+    posts: Mutex<HashMap<SiteRef, Slot>>,
+}
+
+impl Resolver {
+    pub fn new(logins: Vec<BoardLogin>) -> Self {
+        Self {
+            client: make_client(),
+            logins,
+            posts: Mutex::default(),
+        }
+    }
+
+    /// Fills in [`Item::original_source`], see [`originals`].
+    pub async fn originals(&self, items: &mut [Item]) {
+        // Items from several sources can point at the same post
+        let mut wanted: HashMap<SiteRef, Vec<&mut Item>> = HashMap::new();
+
+        for item in items
+            .iter_mut()
+            .filter(|item| item.original_source.is_none())
+        {
+            if let Some(post) = item.site_ref.clone().filter(SiteRef::is_board) {
+                wanted.entry(post).or_default().push(item);
+            }
+        }
+
+        // Built up front, as mapping the stream trips up the compiler on whether the future is `Send`
+        let lookups: Vec<_> = wanted
+            .into_iter()
+            .map(|(post, items)| async move {
+                let original = self.original(&post).await;
+
+                for item in items {
+                    item.original_source.clone_from(&original);
+                }
+            })
+            .collect();
+
+        stream::iter(lookups)
+            .buffer_unordered(CONCURRENCY)
+            .collect::<()>()
+            .await;
+    }
+
+    /// The original source of `post`, fetching it unless it has been already.
+    async fn original(&self, post: &SiteRef) -> Option<String> {
+        let slot = Arc::clone(
+            self.posts
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(post.clone())
+                .or_default(),
+        );
+
+        let mut slot = slot.lock().await;
+
+        if let Some(original) = &*slot {
+            return original.clone();
+        }
+
+        let original = match sources(&self.client, post, self.login(post)).await {
+            Ok(sources) => link::original_source(sources),
+            Err(e) => {
+                warn!(
+                    ?e,
+                    post = post.url(),
+                    "Failed to look up the original source"
+                );
+                None
+            }
+        };
+
+        *slot = Some(original.clone());
+        original
+    }
+
+    fn login(&self, post: &SiteRef) -> Option<&BoardLogin> {
+        self.logins.iter().find(|login| login.site == post.site())
+    }
+}
+
+/// The sources listed on a board post.
+async fn sources(
+    client: &Client,
+    post: &SiteRef,
+    login: Option<&BoardLogin>,
+) -> Result<Vec<String>, Error> {
+    // Build the request
+
+    let req = match post {
+        SiteRef::Danbooru { post_id } => {
+            client.get(format!("https://danbooru.donmai.us/posts/{post_id}.json"))
+        }
+        SiteRef::E621 { post_id } => client.get(format!("https://e621.net/posts/{post_id}.json")),
+        SiteRef::Gelbooru { post_id } => client
+            .get("https://gelbooru.com/index.php")
+            .query(&[
+                ("page", "dapi"),
+                ("s", "post"),
+                ("q", "index"),
+                ("json", "1"),
+            ])
+            .query(&[("id", post_id)]),
+        SiteRef::Yandere { post_id } => client
+            .get("https://yande.re/post.json")
+            .query(&[("tags", format!("id:{post_id}"))]),
+        SiteRef::Konachan { post_id } => client
+            .get("https://konachan.com/post.json")
+            .query(&[("tags", format!("id:{post_id}"))]),
+        _ => return Ok(Vec::new()),
+    };
+
+    let req = authenticate(req, login);
+
+    // Send the request
+
+    let text = req.send().await?.error_for_status()?.text().await?;
+
+    debug!(post = post.url(), "Got post");
+
+    // Parse the response
+
+    let sources = match post {
+        SiteRef::E621 { .. } => serde_json::from_str::<E621Response>(&text)?.post.sources,
+        SiteRef::Gelbooru { .. } => serde_json::from_str::<GelbooruResponse>(&text)?
+            .post
+            .into_iter()
+            .map(|post| post.source)
+            .collect(),
+        SiteRef::Yandere { .. } | SiteRef::Konachan { .. } => {
+            serde_json::from_str::<Vec<Post>>(&text)?
+                .into_iter()
+                .map(|post| post.source)
+                .collect()
+        }
+        _ => vec![serde_json::from_str::<Post>(&text)?.source],
+    };
+
+    Ok(sources)
+}
+
+fn authenticate(req: RequestBuilder, login: Option<&BoardLogin>) -> RequestBuilder {
+    match login {
+        Some(login) => req.basic_auth(&login.username, Some(&login.api_key)),
+        None => req,
+    }
+}
+
+/// A post on Danbooru, Gelbooru, yande.re or Konachan, which all list a single source.
+#[derive(Debug, Deserialize)]
+struct Post {
+    #[serde(default)]
+    source: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GelbooruResponse {
+    #[serde(default)]
+    post: Vec<Post>,
+}
+
+#[derive(Debug, Deserialize)]
+struct E621Response {
+    post: E621Post,
+}
+
+#[derive(Debug, Deserialize)]
+struct E621Post {
+    #[serde(default)]
+    sources: Vec<String>,
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::source::{Item, SourceId};
+
+/// How much of the gap to a certain match is closed by each further source that agrees,
+/// scaled by that source's own confidence. Less than one, as sources often index the same sites.
+const AGREEMENT: f32 = 0.5;
+
+/// Confidences at or above this are [`Confidence::High`].
+const HIGH: f32 = 0.8;
+
+/// Confidences at or above this are [`Confidence::Medium`].
+const MEDIUM: f32 = 0.5;
+
+/// The curve for IQDB scores given as a percentage, by boorus and self-hosted servers.
+const IQDB_PERCENT: [(f32, f32); 5] = [
+    (60.0, 0.0),
+    (80.0, 0.5),
+    (90.0, 0.85),
+    (95.0, 0.95),
+    (100.0, 0.99),
+];
+
+/// How sure a [`Scorer`] is of a match, for deciding what to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// Probably not the same image.
+    Low,
+    /// Possibly the same image, worth checking by eye.
+    Medium,
+    /// Almost certainly the same image.
+    High,
+}
+
+impl Confidence {
+    /// The bucket a confidence from 0 to 1 falls in, with the default cut-offs of `0.8` and `0.5`.
+    #[must_use]
+    pub fn of(confidence: f32) -> Self {
+        if confidence >= HIGH {
+            Self::High
+        } else if confidence >= MEDIUM {
+            Self::Medium
+        } else {
+            Self::Low
+        }
+    }
+}
+
+/// Turns the similarities each source reports into confidences that can be compared across sources.
+///
+/// Similarities aren't comparable as they are. A `SauceNao` 70 is weak, an IQDB 90 is strong,
+/// and a `FuzzySearch` distance of 0 is near-certain. Used by [`merge_with`](crate::aggregate::merge_with).
+pub trait Scorer {
+    /// How sure `source` is that `item` is the image that was searched for, from 0 to 1.
+    fn confidence(&self, source: SourceId, item: &Item) -> f32;
+
+    /// Combines the confidences of several sources that found the same work into one, from 0 to 1.
+    ///
+    /// The default starts from the most confident source, and each other source closes part of the gap to 1.
+    fn combine(&self, confidences: &[f32]) -> f32 {
+        let mut confidences = confidences.to_vec();
+        confidences.sort_by(|a, b| b.total_cmp(a));
+
+        let Some((best, others)) = confidences.split_first() else {
+            return 0.0;
+        };
+
+        let doubt = others.iter().fold(1.0 - best.clamp(0.0, 1.0), |doubt, c| {
+            doubt * AGREEMENT.mul_add(-c.clamp(0.0, 1.0), 1.0)
+        });
+
+        1.0 - doubt
+    }
+
+    /// The bucket a confidence falls in. The default is [`Confidence::of`].
+    fn bucket(&self, confidence: f32) -> Confidence {
+        Confidence::of(confidence)
+    }
+}
+
+/// Maps the similarities of a single source onto confidences, for [`Calibrated`].
+///
+/// Made of points from similarity to confidence, with straight lines between them.
+/// Similarities below the first point or above the last take that point's confidence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    points: Vec<(f32, f32)>,
+    unscored: f32,
+}
+
+impl Curve {
+    /// Creates a curve through `points`, each a similarity and the confidence it maps to.
+    ///
+    /// Items without a similarity get a confidence of 0 unless set with [`Curve::unscored`].
+    #[must_use]
+    pub fn new(points: impl Into<Vec<(f32, f32)>>) -> Self {
+        let mut points = points.into();
+        points.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        Self {
+            points,
+            unscored: 0.0,
+        }
+    }
+
+    /// A curve for a source that never reports a similarity, giving every item the same confidence.
+    #[must_use]
+    pub const fn flat(confidence: f32) -> Self {
+        Self {
+            points: Vec::new(),
+            unscored: confidence,
+        }
+    }
+
+    /// Sets the confidence of items without a similarity, those with a negative one.
+    #[must_use]
+    pub const fn unscored(mut self, confidence: f32) -> Self {
+        self.unscored = confidence;
+        self
+    }
+
+    /// The confidence for `similarity`, from 0 to 1.
+    #[must_use]
+    pub fn confidence(&self, similarity: f32) -> f32 {
+        if similarity < 0.0 || self.points.is_empty() {
+            return self.unscored.clamp(0.0, 1.0);
+        }
+
+        let after = self.points.partition_point(|(x, _)| *x < similarity);
+
+        let confidence = match (
+            after.checked_sub(1).map(|i| self.points[i]),
+            self.points.get(after),
+        ) {
+            (Some((x0, y0)), Some(&(x1, y1))) if x1 > x0 => {
+                y0 + (y1 - y0) * (similarity - x0) / (x1 - x0)
+            }
+            (_, Some(&(_, y))) | (Some((_, y)), None) => y,
+            (None, None) => self.unscored,
+        };
+
+        confidence.clamp(0.0, 1.0)
+    }
+}
+
+/// The default [`Scorer`], with a [`Curve`] for each built-in source.
+///
+/// The curves are set so that about `0.8` is where a match is usually right, and `0.5` where it's a coin toss.
+/// Sources are looked up by [`Source::id`](crate::source::Source::id) and then, for items with
+/// [`Item::details`], by the kind of source, so a renamed [`Booru`](crate::source::booru::Booru)
+/// still gets the booru curve. Sources without a curve are assumed to report a percentage.
+#[derive(Debug, Clone)]
+pub struct Calibrated {
+    curves: HashMap<SourceId, Curve>,
+    fallback: Curve,
+}
+
+impl Calibrated {
+    /// Creates a [`Calibrated`] scorer with the built-in curves.
+    #[must_use]
+    pub fn new() -> Self {
+        let curves = [
+            // Anything under 55 is noise, and 70 is weak
+            (
+                "saucenao",
+                Curve::new([
+                    (55.0, 0.0),
+                    (70.0, 0.3),
+                    (80.0, 0.6),
+                    (90.0, 0.85),
+                    (95.0, 0.95),
+                    (100.0, 0.99),
+                ]),
+            ),
+            // A fraction rather than a percentage, and strong from 0.9
+            (
+                "iqdb",
+                Curve::new([
+                    (0.6, 0.0),
+                    (0.8, 0.5),
+                    (0.9, 0.85),
+                    (0.95, 0.95),
+                    (1.0, 0.99),
+                ]),
+            ),
+            // The same scale as IQDB, as a percentage
+            ("booru", Curve::new(IQDB_PERCENT)),
+            ("danbooru", Curve::new(IQDB_PERCENT)),
+            ("e621", Curve::new(IQDB_PERCENT)),
+            ("iqdb_server", Curve::new(IQDB_PERCENT)),
+            // One over the hash distance plus one, so 1 is a distance of 0 and 0.5 a distance of 1
+            (
+                "fuzzysearch",
+                Curve::new([(0.1, 0.1), (0.2, 0.5), (0.34, 0.8), (0.5, 0.9), (1.0, 0.99)]),
+            ),
+            // trace.moe says anything under 90 is most likely wrong
+            (
+                "tracemoe",
+                Curve::new([
+                    (80.0, 0.0),
+                    (87.0, 0.3),
+                    (90.0, 0.6),
+                    (95.0, 0.9),
+                    (100.0, 0.99),
+                ]),
+            ),
+            (
+                "fluffle",
+                Curve::new([(70.0, 0.0), (85.0, 0.5), (95.0, 0.9), (100.0, 0.99)]),
+            ),
+            // The share of the 64 hash bits that match
+            (
+                "local",
+                Curve::new([(75.0, 0.0), (84.0, 0.5), (92.0, 0.9), (100.0, 0.99)]),
+            ),
+            // Looks for similar images by default, not only copies of the file, so only worth a look
+            ("ehentai", Curve::flat(0.6)),
+            ("ascii2d", Curve::flat(0.3)),
+            ("yandex", Curve::flat(0.2)),
+            ("bing", Curve::flat(0.2)),
+        ];
+
+        Self {
+            curves: curves.into_iter().collect(),
+            fallback: Curve::new([(50.0, 0.0), (100.0, 1.0)]).unscored(0.1),
+        }
+    }
+
+    /// Uses `curve` for the similarities of `source`, replacing the built-in one.
+    #[must_use]
+    pub fn curve(mut self, source: SourceId, curve: Curve) -> Self {
+        self.curves.insert(source, curve);
+        self
+    }
+
+    /// Uses `curve` for sources without a curve of their own.
+    #[must_use]
+    pub fn fallback(mut self, curve: Curve) -> Self {
+        self.fallback = curve;
+        self
+    }
+}
+
+impl Default for Calibrated {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scorer for Calibrated {
+    fn confidence(&self, source: SourceId, item: &Item) -> f32 {
+        self.curves
+            .get(source)
+            .or_else(|| self.curves.get(item.details.as_ref()?.source()))
+            .unwrap_or(&self.fallback)
+            .confidence(item.similarity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calibrated, Confidence, Curve, Scorer};
+    use crate::source::Item;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn curve_interpolates_between_points() {
+        let curve = Curve::new([(50.0, 0.0), (100.0, 1.0)]);
+
+        assert!(close(curve.confidence(50.0), 0.0));
+        assert!(close(curve.confidence(75.0), 0.5));
+        assert!(close(curve.confidence(90.0), 0.8));
+        assert!(close(curve.confidence(100.0), 1.0));
+    }
+
+    #[test]
+    fn curve_sorts_its_points() {
+        let curve = Curve::new([(100.0, 1.0), (0.0, 0.0), (50.0, 0.2)]);
+
+        assert!(close(curve.confidence(25.0), 0.1));
+        assert!(close(curve.confidence(75.0), 0.6));
+    }
+
+    #[test]
+    fn curve_clamps_at_the_ends() {
+        let curve = Curve::new([(60.0, 0.2), (90.0, 0.9)]);
+
+        assert!(close(curve.confidence(0.0), 0.2));
+        assert!(close(curve.confidence(100.0), 0.9));
+
+        // Confidences outside 0 to 1 are clamped too
+        let curve = Curve::new([(0.0, -1.0), (100.0, 2.0)]);
+
+        assert!(close(curve.confidence(0.0), 0.0));
+        assert!(close(curve.confidence(100.0), 1.0));
+    }
+
+    #[test]
+    fn curve_gives_negative_similarities_the_unscored_confidence() {
+        let curve = Curve::new([(0.0, 0.5), (100.0, 1.0)]);
+
+        assert!(close(curve.confidence(-1.0), 0.0));
+        assert!(close(curve.unscored(0.3).confidence(-1.0), 0.3));
+        assert!(close(Curve::flat(0.4).confidence(-1.0), 0.4));
+        assert!(close(Curve::flat(0.4).confidence(80.0), 0.4));
+    }
+
+    #[test]
+    fn combine_handles_no_sources() {
+        assert!(close(Calibrated::new().combine(&[]), 0.0));
+    }
+
+    #[test]
+    fn combine_keeps_a_single_source() {
+        assert!(close(Calibrated::new().combine(&[0.7]), 0.7));
+    }
+
+    #[test]
+    fn combine_raises_the_score_when_sources_agree() {
+        let scorer = Calibrated::new();
+
+        let one = scorer.combine(&[0.7]);
+        let two = scorer.combine(&[0.7, 0.6]);
+        let three = scorer.combine(&[0.6, 0.7, 0.5]);
+
+        assert!(two > one);
+        assert!(three > two);
+        assert!(three < 1.0);
+
+        // The order the sources come in doesn't matter
+        assert!(close(scorer.combine(&[0.6, 0.7]), two));
+    }
+
+    #[test]
+    fn confidence_of_buckets_at_the_boundaries() {
+        assert_eq!(Confidence::of(0.0), Confidence::Low);
+        assert_eq!(Confidence::of(0.499), Confidence::Low);
+        assert_eq!(Confidence::of(0.5), Confidence::Medium);
+        assert_eq!(Confidence::of(0.799), Confidence::Medium);
+        assert_eq!(Confidence::of(0.8), Confidence::High);
+        assert_eq!(Confidence::of(1.0), Confidence::High);
+    }
+
+    #[test]
+    fn calibrated_puts_sources_on_the_same_scale() {
+        let scorer = Calibrated::new();
+        let item = |similarity| Item::new("https://example.com", similarity);
+
+        // An IQDB fraction and a booru percentage mean the same
+        assert!(close(
+            scorer.confidence("iqdb", &item(0.9)),
+            scorer.confidence("danbooru", &item(90.0))
+        ));
+
+        // E-Hentai searches for similar images by default, so it's only worth a look
+        assert_eq!(
+            Confidence::of(scorer.confidence("ehentai", &item(-1.0))),
+            Confidence::Medium
+        );
+
+        // Unknown sources are assumed to report a percentage
+        assert!(close(scorer.confidence("other", &item(75.0)), 0.5));
+    }
+
+    #[cfg(feature = "booru")]
+    #[test]
+    fn calibrated_scores_renamed_sources_by_their_kind() {
+        use crate::source::{Details, booru::Post};
+
+        let scorer = Calibrated::new();
+        let post = Post {
+            id: 1,
+            md5: None,
+            rating: None,
+            sources: Vec::new(),
+            artists: Vec::new(),
+            tags: Vec::new(),
+            file_url: None,
+        };
+        let item = Item {
+            details: Some(Details::Booru(post)),
+            ..Item::new("https://safebooru.donmai.us/posts/1", 90.0)
+        };
+
+        assert!(close(
+            scorer.confidence("safebooru", &item),
+            scorer.confidence("danbooru", &item)
+        ));
+        // Without details there's nothing to tell the kind by
+        let plain = Item::new("https://safebooru.donmai.us/posts/1", 90.0);
+        assert!(close(scorer.confidence("safebooru", &plain), 0.8));
+
+        // A curve for the name itself still comes first
+        let scorer = scorer.curve("safebooru", Curve::flat(0.1));
+        assert!(close(scorer.confidence("safebooru", &item), 0.1));
+    }
+}
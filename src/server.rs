@@ -0,0 +1,846 @@
+use std::{
+    error::Error as StdError,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, Router,
+    extract::{FromRequest, Multipart, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use reqwest::{
+    Client, Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::{Attempt, Policy},
+};
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, lookup_host};
+use tracing::debug;
+#[cfg(feature = "tracemoe")]
+use tracing::warn;
+
+#[cfg(feature = "tracemoe")]
+use crate::source::tracemoe::{Quota, TraceMoe};
+use crate::{
+    aggregate::{Aggregator, Checked},
+    client_builder,
+    error::Error,
+    input::{DEFAULT_MAX_SIZE, DownloadOptions, ImageData, ImageInput},
+    source::{Output, SourceId},
+};
+
+/// How many redirects are followed when downloading a link, as many as `reqwest` follows by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// Serves an [`Aggregator`] over HTTP, so the API keys and rate limit live in one place.
+///
+/// # Routes
+/// - `POST /search` takes either JSON like `{"url": "https://..."}`, or a multipart upload
+///   with the image in a `file` field. It answers with `{"results": [...]}`, holding the
+///   serialized [`Output`] or the error for each source.
+/// - `GET /quota` reports how much of the [`RateLimit`] is left, and the trace.moe quota if
+///   [`Server::tracemoe_quota`] was set.
+///
+/// Searches beyond the rate limit are answered with `429 Too Many Requests` and a `Retry-After` header.
+///
+/// As the server fetches the links it's given, links to loopback, private and link-local addresses
+/// are answered with `403 Forbidden`, so clients can't reach the server's own network through it.
+/// The server downloads the image itself and uploads it to each source, checking the address
+/// it connects to for every redirect, so a public link can't redirect there either.
+/// See [`Server::allow_private_urls`] to turn that off.
+#[derive(Debug)]
+pub struct Server {
+    aggregator: Aggregator,
+    limit: Option<Limiter>,
+    allow_private: bool,
+    #[cfg(feature = "tracemoe")]
+    tracemoe: Option<TraceMoe>,
+}
+
+/// How many searches the [`Server`] allows in a window of time, across every client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// How many searches are allowed in each window.
+    pub searches: u32,
+    /// How long each window lasts.
+    pub per: Duration,
+}
+
+#[derive(Debug)]
+struct Limiter {
+    limit: RateLimit,
+    window: Mutex<Window>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant,
+    used: u32,
+}
+
+impl Limiter {
+    /// Starts a new window if the current one is over.
+    fn current(&self) -> std::sync::MutexGuard<'_, Window> {
+        // A panic while holding the lock can't leave the counter in a bad state
+        let mut window = self
+            .window
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        if window.started.elapsed() >= self.limit.per {
+            *window = Window {
+                started: Instant::now(),
+                used: 0,
+            };
+        }
+
+        window
+    }
+
+    /// Takes a search from the window, or says how long until the next window if there are none left.
+    fn take(&self) -> Result<(), Duration> {
+        let mut window = self.current();
+
+        let taken = if window.used >= self.limit.searches {
+            Err(self.limit.per.saturating_sub(window.started.elapsed()))
+        } else {
+            window.used += 1;
+            Ok(())
+        };
+        drop(window);
+
+        taken
+    }
+
+    fn status(&self) -> RateLimitStatus {
+        let window = self.current();
+
+        RateLimitStatus {
+            limit: self.limit.searches,
+            remaining: self.limit.searches.saturating_sub(window.used),
+            reset_in: self
+                .limit
+                .per
+                .saturating_sub(window.started.elapsed())
+                .as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchRequest {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse {
+    results: Vec<SourceResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct SourceResult {
+    source: SourceId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<Checked> for SourceResult {
+    fn from((source, res): Checked) -> Self {
+        match res {
+            Ok(output) => Self {
+                source,
+                output: Some(output),
+                error: None,
+            },
+            Err(e) => Self {
+                source,
+                output: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct QuotaResponse {
+    rate_limit: Option<RateLimitStatus>,
+    #[cfg(feature = "tracemoe")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracemoe: Option<Quota>,
+}
+
+#[derive(Debug, Serialize)]
+struct RateLimitStatus {
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the window starts over.
+    reset_in: u64,
+}
+
+/// An error answered as `{"error": "..."}` with the given status.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+    retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> Self {
+        let status = match e {
+            Error::LinkIsNotImage | Error::UnsupportedFormat(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+
+        Self::new(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut resp = (
+            self.status,
+            Json(serde_json::json!({ "error": self.message })),
+        )
+            .into_response();
+
+        if let Some(retry_after) = self.retry_after {
+            // Round up, so clients don't come back a moment too early
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            resp.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+
+        resp
+    }
+}
+
+impl Server {
+    /// Creates a [`Server`] for the sources in `aggregator`, with no rate limit.
+    #[must_use]
+    pub const fn new(aggregator: Aggregator) -> Self {
+        Self {
+            aggregator,
+            limit: None,
+            allow_private: false,
+            #[cfg(feature = "tracemoe")]
+            tracemoe: None,
+        }
+    }
+
+    /// Searches links to loopback, private and link-local addresses too, which are refused by default.
+    ///
+    /// Only for servers that aren't reachable by untrusted clients.
+    #[must_use]
+    pub const fn allow_private_urls(mut self) -> Self {
+        self.allow_private = true;
+        self
+    }
+
+    /// Limits how many searches are made, across every client.
+    #[must_use]
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.limit = Some(Limiter {
+            limit,
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                used: 0,
+            }),
+        });
+        self
+    }
+
+    /// Reports the quota of this [`TraceMoe`] source from `GET /quota`.
+    #[cfg(feature = "tracemoe")]
+    #[must_use]
+    pub fn tracemoe_quota(mut self, tracemoe: TraceMoe) -> Self {
+        self.tracemoe = Some(tracemoe);
+        self
+    }
+
+    /// The routes, for serving alongside others or with extra middleware.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/search", post(search))
+            .route("/quota", get(quota))
+            .layer(axum::extract::DefaultBodyLimit::max(
+                usize::try_from(DEFAULT_MAX_SIZE).unwrap_or(usize::MAX),
+            ))
+            .with_state(Arc::new(self))
+    }
+
+    /// Serves the routes on `listener` until the process is stopped.
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the listener fails
+    pub async fn serve(self, listener: TcpListener) -> Result<(), Error> {
+        axum::serve(listener, self.router()).await?;
+
+        Ok(())
+    }
+}
+
+async fn search(
+    State(server): State<Arc<Server>>,
+    request: Request,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let input = read_input(request).await?;
+
+    // Before anything else, so clients past the limit can't make the server look up hosts
+    if let Some(limiter) = &server.limit {
+        limiter.take().map_err(|retry_after| ApiError {
+            retry_after: Some(retry_after),
+            ..ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Rate limit reached")
+        })?;
+    }
+
+    let results = match input {
+        ImageInput::Url(url) if server.allow_private => server.aggregator.check(&url).await?,
+        ImageInput::Url(url) => {
+            check_public(&url).await?;
+
+            let options = server.aggregator.download_options();
+            let data = download_public(&public_client(is_public), &url, &options).await?;
+
+            server.aggregator.check_input(&data.into()).await
+        }
+        input @ ImageInput::Data(_) => server.aggregator.check_input(&input).await,
+    };
+
+    debug!(sources = results.len(), "Searched");
+
+    Ok(Json(SearchResponse {
+        results: results.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Reads either a JSON body with a `url`, or a multipart upload with a `file`.
+async fn read_input(request: Request) -> Result<ImageInput, ApiError> {
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"));
+
+    if !is_multipart {
+        let Json(body) = Json::<SearchRequest>::from_request(request, &())
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+
+        return Ok(ImageInput::Url(body.url));
+    }
+
+    let mut multipart = Multipart::from_request(request, &())
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let origin = field.file_name().unwrap_or("upload").to_string();
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+
+        return Ok(ImageData::from_bytes(origin, bytes.to_vec())?.into());
+    }
+
+    Err(ApiError::new(
+        StatusCode::BAD_REQUEST,
+        "Expected the image in a `file` field",
+    ))
+}
+
+/// Refuses links that aren't `http` or `https`, or whose host is or resolves to an address that isn't public.
+///
+/// This only answers early with a clear error. The host is resolved again when it's fetched, so
+/// [`public_client`] checks the addresses it connects to as well, along with every redirect.
+async fn check_public(url: &str) -> Result<(), ApiError> {
+    let parsed = Url::parse(url)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid link: {e}")))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Only http and https links can be searched",
+        ));
+    }
+
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "The link has no host"))?;
+
+    // IPv6 hosts keep their brackets
+    let addresses = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => lookup_host((host, parsed.port_or_known_default().unwrap_or(80)))
+            .await
+            .map_err(|e| {
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("Could not resolve {host}: {e}"),
+                )
+            })?
+            .map(|address| address.ip())
+            .collect(),
+    };
+
+    if addresses.iter().all(|ip| is_public(*ip)) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Links to private addresses can't be searched",
+        ))
+    }
+}
+
+/// Refused by [`public_client`], for a redirect or a host that leads to an address that isn't public.
+#[derive(Debug)]
+struct PrivateAddress;
+
+impl fmt::Display for PrivateAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The link leads to a private address")
+    }
+}
+
+impl StdError for PrivateAddress {}
+
+/// Resolves hosts like `reqwest` does, but fails if any of the addresses isn't allowed.
+///
+/// Checking at the time of connecting covers names that change their address after [`check_public`].
+#[derive(Debug)]
+struct PublicOnly(fn(IpAddr) -> bool);
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed = self.0;
+
+        Box::pin(async move {
+            // The port is filled in by the connector
+            let addresses: Vec<SocketAddr> = lookup_host((name.as_str(), 0)).await?.collect();
+
+            if addresses.iter().all(|address| allowed(address.ip())) {
+                Ok(Box::new(addresses.into_iter()) as Addrs)
+            } else {
+                Err(Box::new(PrivateAddress) as Box<dyn StdError + Send + Sync>)
+            }
+        })
+    }
+}
+
+/// A client that only connects to addresses `allowed` accepts, checking every redirect along the way.
+///
+/// Hosts go through [`PublicOnly`], and redirects to IP addresses, which aren't resolved, are checked here.
+fn public_client(allowed: fn(IpAddr) -> bool) -> Client {
+    let policy = Policy::custom(move |attempt: Attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+
+        let url = attempt.url();
+        let refused = !matches!(url.scheme(), "http" | "https")
+            || url.host_str().is_none_or(|host| {
+                // IPv6 hosts keep their brackets
+                host.trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse()
+                    .is_ok_and(|ip| !allowed(ip))
+            });
+
+        if refused {
+            attempt.error(PrivateAddress)
+        } else {
+            attempt.follow()
+        }
+    });
+
+    client_builder()
+        .redirect(policy)
+        .dns_resolver(Arc::new(PublicOnly(allowed)))
+        .build()
+        .expect("failed to build client")
+}
+
+/// Downloads the image at `url` with a [`public_client`], answering `403 Forbidden` if it leads somewhere private.
+async fn download_public(
+    client: &Client,
+    url: &str,
+    options: &DownloadOptions,
+) -> Result<ImageData, ApiError> {
+    let resp = match client.get(url).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            let mut cause: Option<&(dyn StdError + 'static)> = Some(&e);
+
+            while let Some(error) = cause {
+                if error.is::<PrivateAddress>() {
+                    return Err(ApiError::new(
+                        StatusCode::FORBIDDEN,
+                        "Links to private addresses can't be searched",
+                    ));
+                }
+
+                cause = error.source();
+            }
+
+            return Err(Error::from(e).into());
+        }
+    };
+
+    Ok(ImageData::from_response(url, resp, options).await?)
+}
+
+/// Whether `ip` is an ordinary address on the internet, rather than a loopback, private,
+/// link-local, shared or otherwise reserved one.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                // Reserved, 240.0.0.0/4
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local())
+        }
+    }
+}
+
+async fn quota(State(server): State<Arc<Server>>) -> Json<QuotaResponse> {
+    #[cfg(feature = "tracemoe")]
+    let tracemoe = match &server.tracemoe {
+        Some(tracemoe) => match tracemoe.me().await {
+            Ok(quota) => Some(quota),
+            Err(e) => {
+                warn!(?e, "Failed to get the trace.moe quota");
+                None
+            }
+        },
+        None => None,
+    };
+
+    Json(QuotaResponse {
+        rate_limit: server.limit.as_ref().map(Limiter::status),
+        #[cfg(feature = "tracemoe")]
+        tracemoe,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use axum::{Router, http::StatusCode, response::Redirect, routing::get};
+    use reqwest::{Client, Response, header, multipart};
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+
+    use super::{
+        ApiError, RateLimit, Server, check_public, download_public, is_public, public_client,
+    };
+    use crate::{aggregate::Aggregator, error::Error, input::DownloadOptions};
+
+    /// The start of a PNG, enough to be recognised.
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    /// Serves `server` on a free local port, returning its base URL.
+    async fn spawn(server: Server) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("binding a local port should work");
+        let address = listener.local_addr().expect("should have an address");
+
+        tokio::spawn(server.serve(listener));
+
+        format!("http://{address}")
+    }
+
+    /// Serves `router` on a free local port, returning its base URL.
+    async fn spawn_router(router: Router) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("binding a local port should work");
+        let address = listener.local_addr().expect("should have an address");
+
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        format!("http://{address}")
+    }
+
+    /// Serves a PNG at `/image.png`, and a redirect to `to` at `/redirect`.
+    async fn spawn_images(to: String) -> String {
+        spawn_router(
+            Router::new()
+                .route(
+                    "/image.png",
+                    get(|| async { ([(header::CONTENT_TYPE, "image/png")], PNG) }),
+                )
+                .route(
+                    "/redirect",
+                    get(move || async move { Redirect::temporary(&to) }),
+                ),
+        )
+        .await
+    }
+
+    async fn search_url(base: &str, url: &str) -> Response {
+        Client::new()
+            .post(format!("{base}/search"))
+            .json(&json!({ "url": url }))
+            .send()
+            .await
+            .expect("the server should answer")
+    }
+
+    async fn upload(base: &str, bytes: &'static [u8]) -> Response {
+        let form =
+            multipart::Form::new().part("file", multipart::Part::bytes(bytes).file_name("a.png"));
+
+        Client::new()
+            .post(format!("{base}/search"))
+            .multipart(form)
+            .send()
+            .await
+            .expect("the server should answer")
+    }
+
+    #[tokio::test]
+    async fn searches_uploads() {
+        let base = spawn(Server::new(Aggregator::new())).await;
+
+        let resp = upload(&base, PNG).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = resp.json().await.expect("should answer with JSON");
+        assert_eq!(body, json!({ "results": [] }));
+    }
+
+    #[tokio::test]
+    async fn rejects_uploads_that_arent_images() {
+        let base = spawn(Server::new(Aggregator::new())).await;
+
+        let resp = upload(&base, b"<html></html>").await;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value = resp.json().await.expect("should answer with JSON");
+        assert!(body["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn lets_public_links_through() {
+        assert!(
+            check_public("https://93.184.215.14/image.png")
+                .await
+                .is_ok()
+        );
+        assert!(
+            check_public("http://[2606:4700::1111]/image.png")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_redirects_to_private_addresses() {
+        // The server the redirect starts from stands in for a public host, as the client
+        // only checks where redirects lead, leaving the link itself to `check_public`
+        let images = spawn_images(String::new()).await;
+        let port = images.rsplit(':').next().expect("should have a port");
+
+        for to in [
+            format!("{images}/image.png"),
+            format!("http://localhost:{port}/image.png"),
+            format!("http://[::1]:{port}/image.png"),
+            "http://169.254.169.254/latest/meta-data/".to_string(),
+        ] {
+            let public = spawn_images(to.clone()).await;
+
+            let res = download_public(
+                &public_client(is_public),
+                &format!("{public}/redirect"),
+                &DownloadOptions::default(),
+            )
+            .await;
+
+            assert!(
+                matches!(&res, Err(e) if e.status == StatusCode::FORBIDDEN),
+                "{to}: {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_redirects_to_allowed_addresses() {
+        let images = spawn_images(String::new()).await;
+        let public = spawn_images(format!("{images}/image.png")).await;
+
+        let data = download_public(
+            &public_client(|_| true),
+            &format!("{public}/redirect"),
+            &DownloadOptions::default(),
+        )
+        .await
+        .expect("the redirect should be followed");
+
+        assert_eq!(data.bytes, PNG);
+    }
+
+    #[tokio::test]
+    async fn refuses_private_links() {
+        let base = spawn(Server::new(Aggregator::new())).await;
+
+        for url in [
+            "http://127.0.0.1/image.png",
+            "http://localhost:8080/image.png",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.1/image.png",
+            "http://[::1]/image.png",
+            "http://[::ffff:192.168.0.1]/image.png",
+        ] {
+            assert_eq!(
+                search_url(&base, url).await.status(),
+                StatusCode::FORBIDDEN,
+                "{url}"
+            );
+        }
+
+        assert_eq!(
+            search_url(&base, "file:///etc/passwd").await.status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn allows_private_links_when_asked() {
+        let base = spawn(Server::new(Aggregator::new()).allow_private_urls()).await;
+
+        let resp = search_url(&base, "http://127.0.0.1/image.png").await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn answers_bad_requests() {
+        let base = spawn(Server::new(Aggregator::new())).await;
+
+        let resp = Client::new()
+            .post(format!("{base}/search"))
+            .multipart(multipart::Form::new().text("other", "field"))
+            .send()
+            .await
+            .expect("the server should answer");
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = Client::new()
+            .post(format!("{base}/search"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body("{}")
+            .send()
+            .await
+            .expect("the server should answer");
+        assert!(resp.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn limits_the_rate_of_searches() {
+        let server = Server::new(Aggregator::new()).rate_limit(RateLimit {
+            searches: 1,
+            per: Duration::from_mins(1),
+        });
+        let base = spawn(server).await;
+
+        assert_eq!(upload(&base, PNG).await.status(), StatusCode::OK);
+
+        let resp = upload(&base, PNG).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key(header::RETRY_AFTER));
+
+        // Links aren't looked at once the limit is reached
+        let resp = search_url(&base, "http://127.0.0.1/image.png").await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn maps_errors_to_statuses() {
+        for (error, status) in [
+            (Error::LinkIsNotImage, StatusCode::UNPROCESSABLE_ENTITY),
+            (Error::TooLarge { limit: 1 }, StatusCode::PAYLOAD_TOO_LARGE),
+            (
+                Error::Generic("upstream".to_string()),
+                StatusCode::BAD_GATEWAY,
+            ),
+        ] {
+            assert_eq!(ApiError::from(error).status, status);
+        }
+    }
+
+    #[test]
+    fn tells_public_addresses_apart() {
+        for ip in ["93.184.215.14", "2606:4700::1111", "100.128.0.1"] {
+            let ip: IpAddr = ip.parse().expect("valid address");
+            assert!(is_public(ip), "{ip}");
+        }
+
+        for ip in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            let ip: IpAddr = ip.parse().expect("valid address");
+            assert!(!is_public(ip), "{ip}");
+        }
+    }
+}
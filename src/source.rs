@@ -1,20 +1,65 @@
 use async_trait::async_trait;
+use serde::Serialize;
 
-use crate::error::Error;
+use crate::{
+    error::Error,
+    input::{Crop, ImageData, ImageInput, UploadLimits},
+    link::SiteRef,
+    resolve::BoardLogin,
+};
+
+/// A macro that creates a &Selector from a string literal.
+#[allow(unused_macros)]
+macro_rules! sel {
+    ($sel:literal) => {
+        &scraper::Selector::parse($sel).expect("invalid selector")
+    };
+}
 
 #[cfg(feature = "saucenao")]
 /// The source for `saucenao.com`. Requires an API key to function.
 pub mod saucenao;
 
+#[cfg(feature = "ascii2d")]
+/// The source for `ascii2d.net`.
+pub mod ascii2d;
+#[cfg(feature = "bing")]
+/// The source for Bing Visual Search, for photos, memes and other general images.
+pub mod bing;
+#[cfg(feature = "booru")]
+/// The source for Danbooru and e621's own IQDB endpoints.
+pub mod booru;
+#[cfg(feature = "ehentai")]
+/// The source for the file search on `e-hentai.org` and `exhentai.org`.
+pub mod ehentai;
+#[cfg(feature = "fluffle")]
+/// The source for `fluffle.xyz`, for furry art.
+pub mod fluffle;
 #[cfg(feature = "fuzzysearch")]
 /// The source for `fuzzysearch.net`.
 pub mod fuzzysearch;
 #[cfg(feature = "iqdb")]
 /// The source for `iqdb.org`.
 pub mod iqdb;
+#[cfg(feature = "iqdb_server")]
+/// The source for self-hosted iqdb servers, such as the one Danbooru runs.
+pub mod iqdb_server;
+#[cfg(feature = "local")]
+/// The source for an index of perceptual hashes kept on this machine.
+pub mod local;
+#[cfg(feature = "tracemoe")]
+/// The source for `trace.moe`, for finding the anime a screenshot is from.
+pub mod tracemoe;
+#[cfg(feature = "yandex")]
+/// The source for `yandex.com`.
+pub mod yandex;
+
+/// A name identifying a source, such as `"saucenao"`.
+pub type SourceId = &'static str;
 
 /// The generic trait implemented by all sources under this module.
-#[async_trait]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 pub trait Source
 where
     Self: Sized,
@@ -22,25 +67,99 @@ where
     /// The argument for [`Source::create`]
     type State;
 
+    /// The name of the source, used to tell results apart when searching several at once.
+    ///
+    /// Shared by every instance, see [`Source::id`] for the name of a single one.
+    const NAME: SourceId;
+
+    /// What the source accepts for uploaded images, see [`Source::check_data`].
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits::DEFAULT;
+
     /// Searches for the source of a given URL.
     async fn check(&self, url: &str) -> Result<Output, Error>;
 
+    /// Searches for the source of an image that has already been downloaded, by uploading it.
+    ///
+    /// The default implementation returns [`Error::UploadUnsupported`].
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let _ = data;
+
+        Err(Error::UploadUnsupported)
+    }
+
+    /// Searches using whichever of [`Source::check`] or [`Source::check_data`] fits the input.
+    async fn check_input(&self, input: &ImageInput) -> Result<Output, Error> {
+        match input {
+            ImageInput::Url(url) => self.check(url).await,
+            ImageInput::Data(data) => self.check_data(data).await,
+        }
+    }
+
+    /// The name of this instance, used to tell results apart when searching several at once.
+    ///
+    /// Defaults to [`Source::NAME`]. Sources that can be set up for different sites override it,
+    /// so that two of them searched together can still be told apart.
+    fn id(&self) -> SourceId {
+        Self::NAME
+    }
+
+    /// A login for the board this source searches, used when looking up the original sources
+    /// of its posts, see [`Aggregator::resolve_originals`](crate::aggregate::Aggregator::resolve_originals).
+    ///
+    /// Defaults to [`None`].
+    fn board_login(&self) -> Option<BoardLogin> {
+        None
+    }
+
     /// Allows for self-modifying the state of the Source, with an additional 'State' parameter that
     /// can be passed in.
     async fn create(argument: Self::State) -> Result<Self, Error>;
 }
 
 /// The output of a Source.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Output {
     /// The original URL provided to the Source.
     pub original_url: String,
     /// The results of the search.
     pub items: Vec<Item>,
+    /// The part of the image that was searched, if borders or screenshot framing were cropped off first.
+    pub crop: Option<Crop>,
+}
+
+impl Output {
+    /// Merges several outputs into one, such as the results for each frame of an animation.
+    ///
+    /// Items for the same work are combined, see [`Item::same_work`], keeping the highest similarity.
+    /// The result is sorted from most to least similar. The first [`Output::crop`] is kept.
+    pub fn merge(original_url: impl Into<String>, outputs: impl IntoIterator<Item = Self>) -> Self {
+        let mut items: Vec<Item> = Vec::new();
+        let mut crop = None;
+
+        for output in outputs {
+            crop = crop.or(output.crop);
+
+            for item in output.items {
+                match items.iter_mut().find(|i| i.same_work(&item)) {
+                    Some(existing) if existing.similarity < item.similarity => *existing = item,
+                    Some(_) => {}
+                    None => items.push(item),
+                }
+            }
+        }
+
+        items.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+
+        Self {
+            original_url: original_url.into(),
+            items,
+            crop,
+        }
+    }
 }
 
 /// An individual item from the results gotten.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Item {
     /// Link to the item. Note: this is not always a direct link to the image, but to a site such as pixiv or danbooru.
     pub link: String,
@@ -49,4 +168,101 @@ pub struct Item {
     /// # Notes
     /// A negative value means that a similarity could not be parsed.
     pub similarity: f32,
+    /// The title of the work, if the source reports one.
+    pub title: Option<String>,
+    /// The author or artist of the work, if the source reports one.
+    pub author: Option<String>,
+    /// The name of the site the link points to, such as `pixiv`, if the source reports one.
+    pub site: Option<String>,
+    /// The site and post the link points to, if it's a site that's recognised.
+    ///
+    /// Set from the link by [`Item::new`].
+    pub site_ref: Option<SiteRef>,
+    /// A link to the artist's own post, if the item is a repost that lists where it came from.
+    ///
+    /// Filled in from what the source reports, or looked up with [`resolve::originals`](crate::resolve::originals).
+    pub original_source: Option<String>,
+    /// Extra information that only the source that found the item provides.
+    pub details: Option<Details>,
+}
+
+impl Item {
+    /// Creates an item for `link`, working out which site and post it points to.
+    ///
+    /// Everything else is left empty, to be filled in with struct update syntax.
+    #[must_use]
+    pub fn new(link: impl Into<String>, similarity: f32) -> Self {
+        let link = link.into();
+
+        Self {
+            site_ref: SiteRef::parse(&link),
+            link,
+            similarity,
+            ..Default::default()
+        }
+    }
+
+    /// Whether both items point to the same work, even if through different links.
+    ///
+    /// Compares the site and ID of each [`Item::site_ref`] if both have one, or the links otherwise.
+    #[must_use]
+    pub fn same_work(&self, other: &Self) -> bool {
+        match (&self.site_ref, &other.site_ref) {
+            (Some(a), Some(b)) => a.site() == b.site() && a.id() == b.id(),
+            _ => self.link == other.link,
+        }
+    }
+}
+
+/// Extra information that only some sources provide, see [`Item::details`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Details {
+    /// The scene found by [`TraceMoe`](tracemoe::TraceMoe).
+    #[cfg(feature = "tracemoe")]
+    TraceMoe(tracemoe::Scene),
+    /// The match found by [`Bing`](bing::Bing).
+    #[cfg(feature = "bing")]
+    Bing(bing::Match),
+    /// The post found by [`Booru`](booru::Booru).
+    #[cfg(feature = "booru")]
+    Booru(booru::Post),
+    /// The match found by [`Fluffle`](fluffle::Fluffle).
+    #[cfg(feature = "fluffle")]
+    Fluffle(fluffle::Match),
+    /// The post found by [`IqdbServer`](iqdb_server::IqdbServer).
+    #[cfg(feature = "iqdb_server")]
+    IqdbServer(iqdb_server::Post),
+    /// The match found by [`LocalIndex`](local::LocalIndex).
+    #[cfg(feature = "local")]
+    Local(local::Match),
+    /// The gallery found by [`EHentai`](ehentai::EHentai).
+    #[cfg(feature = "ehentai")]
+    EHentai(ehentai::Gallery),
+}
+
+impl Details {
+    /// The [`Source::NAME`] of the kind of source that found the item, whatever [`Source::id`] it has.
+    #[must_use]
+    #[allow(clippy::uninhabited_references)]
+    pub const fn source(&self) -> SourceId {
+        // Without any of these sources enabled there are no variants, and only `*self` can be matched
+        match *self {
+            #[cfg(feature = "tracemoe")]
+            Self::TraceMoe(_) => tracemoe::TraceMoe::NAME,
+            #[cfg(feature = "bing")]
+            Self::Bing(_) => bing::Bing::NAME,
+            #[cfg(feature = "booru")]
+            Self::Booru(_) => booru::Booru::NAME,
+            #[cfg(feature = "fluffle")]
+            Self::Fluffle(_) => fluffle::Fluffle::NAME,
+            #[cfg(feature = "iqdb_server")]
+            Self::IqdbServer(_) => iqdb_server::IqdbServer::NAME,
+            #[cfg(feature = "local")]
+            Self::Local(_) => local::LocalIndex::NAME,
+            #[cfg(feature = "ehentai")]
+            Self::EHentai(_) => ehentai::EHentai::NAME,
+        }
+    }
 }
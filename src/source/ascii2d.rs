@@ -0,0 +1,287 @@
+use async_trait::async_trait;
+use reqwest::{Client, Response, Url, header};
+use scraper::{ElementRef, Html};
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Item, Output, Source, SourceId};
+
+const BASE_URL: &str = "https://ascii2d.net";
+
+/// The [`Ascii2d`] source.
+///
+/// Works with `ascii2d.net`. Both the colour search and the follow-up feature ("bovw")
+/// search are scraped, with the colour results coming first.
+///
+/// # Notes
+/// ascii2d doesn't report a similarity, so every [`Item::similarity`] is negative.
+#[derive(Debug, Default)]
+pub struct Ascii2d {
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for Ascii2d {
+    type State = ();
+
+    const NAME: SourceId = "ascii2d";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 10 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Check whether we're dealing with an image
+        self.validator.validate(&client, url).await?;
+
+        // Build the request
+
+        let mut search = Url::parse(BASE_URL).expect("invalid base URL");
+        search
+            .path_segments_mut()
+            .expect("base URL can't have a path")
+            .extend(["search", "url", url]);
+
+        // ascii2d redirects to the colour search results
+        let resp = client.get(search).send().await?.error_for_status()?;
+
+        Self::follow(&client, url, resp).await
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Uploading needs the CSRF token from the front page, along with its session cookie
+
+        let home = client.get(BASE_URL).send().await?.error_for_status()?;
+
+        let cookies = home
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok()?.split(';').next())
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let text = home.text().await?;
+        let token = Self::find_token(&text)
+            .ok_or_else(|| Error::Generic("Could not find the ascii2d upload token".to_string()))?;
+
+        // Build the request
+
+        let form = reqwest::multipart::Form::new()
+            .text("utf8", "✓")
+            .text("authenticity_token", token)
+            .part("file", data.to_part()?);
+
+        let resp = client
+            .post(format!("{BASE_URL}/search/file"))
+            .header(header::COOKIE, cookies)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Self::follow(&client, &data.origin, resp).await
+    }
+
+    async fn create(_: Self::State) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+}
+
+impl Ascii2d {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Parses the colour search results, then fetches and parses the matching feature search.
+    async fn follow(client: &Client, original_url: &str, color: Response) -> Result<Output, Error> {
+        let bovw_url = Self::bovw_url(color.url().as_str());
+
+        let color = Self::parse(original_url, &color.text().await?);
+
+        // Fetching the same page again would only duplicate the colour results
+        let Some(bovw_url) = bovw_url else {
+            warn!("No colour search to follow, only returning its results");
+            return Ok(color);
+        };
+
+        debug!(%bovw_url, "fetching feature search");
+
+        let bovw = match Self::fetch(client, &bovw_url).await {
+            Ok(text) => Self::parse(original_url, &text),
+            Err(e) => {
+                warn!(?e, "Feature search failed, only returning colour results");
+                return Ok(color);
+            }
+        };
+
+        // The two searches often find the same works
+        Ok(Output::merge(original_url, [color, bovw]))
+    }
+
+    /// The feature search matching a colour search, or `None` if `color` isn't one.
+    fn bovw_url(color: &str) -> Option<String> {
+        color
+            .contains("/color/")
+            .then(|| color.replacen("/color/", "/bovw/", 1))
+    }
+
+    /// Fetches the page at `url`, failing on error statuses.
+    async fn fetch(client: &Client, url: &str) -> Result<String, Error> {
+        Ok(client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    fn find_token(text: &str) -> Option<String> {
+        let html = Html::parse_document(text);
+
+        let input = html.select(sel!("input[name=authenticity_token]")).next()?;
+
+        input.value().attr("value").map(ToString::to_string)
+    }
+
+    fn parse(original_url: &str, text: &str) -> Output {
+        let html = Html::parse_document(text);
+
+        let items: Vec<Item> = html
+            .select(sel!(".item-box"))
+            .filter_map(Self::harvest_item)
+            .collect();
+
+        Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        }
+    }
+
+    fn harvest_item(item: ElementRef) -> Option<Item> {
+        debug!("selecting .detail-box h6");
+        let details = item.select(sel!(".detail-box h6")).next()?;
+
+        let links: Vec<ElementRef> = details.select(sel!("a")).collect();
+
+        debug!("grabbing work link");
+        let work = links.first()?;
+        let link = work.value().attr("href")?.to_string();
+        let title = work.text().collect::<String>().trim().to_string();
+
+        debug!("grabbing author");
+        let author = links
+            .get(1)
+            .map(|a| a.text().collect::<String>().trim().to_string());
+
+        debug!("grabbing site name");
+        let site = details
+            .select(sel!("small"))
+            .next()
+            .map(|s| s.text().collect::<String>().trim().to_string());
+
+        Some(Item {
+            title: Some(title).filter(|t| !t.is_empty()),
+            author: author.filter(|a| !a.is_empty()),
+            site: site.filter(|s| !s.is_empty()),
+            ..Item::new(link, -1.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ascii2d;
+
+    // A trimmed colour search page from ascii2d, for checking the parsing offline.
+    const FIXTURE: &str = include_str!("../../tests/fixtures/ascii2d.html");
+    const ORIGINAL_URL: &str = "https://example.com/summer.jpg";
+
+    #[test]
+    fn parses_results_skipping_the_query_and_empty_boxes() {
+        let output = Ascii2d::parse(ORIGINAL_URL, FIXTURE);
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+
+        let links: Vec<_> = output.items.iter().map(|i| i.link.as_str()).collect();
+        assert_eq!(
+            links,
+            [
+                "https://www.pixiv.net/artworks/81234567",
+                "https://twitter.com/minato_art/status/1300000000000000001",
+                "https://seiga.nicovideo.jp/seiga/im10987654",
+            ]
+        );
+        assert!(output.items.iter().all(|i| i.similarity < 0.0));
+    }
+
+    #[test]
+    fn parses_title_author_and_site() {
+        let output = Ascii2d::parse(ORIGINAL_URL, FIXTURE);
+
+        let pixiv = &output.items[0];
+        assert_eq!(pixiv.title.as_deref(), Some("夏の終わり"));
+        assert_eq!(pixiv.author.as_deref(), Some("みなと"));
+        assert_eq!(pixiv.site.as_deref(), Some("pixiv"));
+        assert!(pixiv.site_ref.is_some());
+
+        // A blank title and a missing author are left empty
+        let twitter = &output.items[1];
+        assert_eq!(twitter.title, None);
+        assert_eq!(twitter.author, None);
+        assert_eq!(twitter.site.as_deref(), Some("twitter"));
+    }
+
+    #[test]
+    fn parses_empty_page() {
+        let output = Ascii2d::parse(ORIGINAL_URL, "<html><body></body></html>");
+
+        assert!(output.items.is_empty());
+    }
+
+    #[test]
+    fn finds_upload_token() {
+        let page =
+            r#"<form><input type="hidden" name="authenticity_token" value="abc123=="></form>"#;
+
+        assert_eq!(Ascii2d::find_token(page).as_deref(), Some("abc123=="));
+        assert_eq!(Ascii2d::find_token("<form></form>"), None);
+    }
+
+    #[test]
+    fn follows_only_colour_searches() {
+        assert_eq!(
+            Ascii2d::bovw_url("https://ascii2d.net/search/color/0c30f4e7b1a0d8a6").as_deref(),
+            Some("https://ascii2d.net/search/bovw/0c30f4e7b1a0d8a6")
+        );
+        assert_eq!(
+            Ascii2d::bovw_url("https://ascii2d.net/search/url/https%3A%2F%2Fexample.com"),
+            None
+        );
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+const SEARCH_URL: &str = "https://www.bing.com/images/api/custom/knowledge";
+
+/// The [`Bing`] source.
+///
+/// Works with Bing Visual Search on `bing.com`, returning the "pages including this image"
+/// list followed by the visually similar images. It's a general-purpose engine, so it's
+/// better suited to photos, memes and product shots than the art-focused sources.
+/// Each [`Item`] links to the page the image was found on, with the [`Match`] in [`Item::details`].
+///
+/// # Notes
+/// Bing doesn't report a similarity, so every [`Item::similarity`] is negative.
+#[derive(Debug, Default)]
+pub struct Bing {
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+/// Which list a [`Match`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    /// A page that includes the searched image.
+    PageIncluding,
+    /// A page with a visually similar image, which may not be the same image.
+    VisualMatch,
+}
+
+/// A match found by [`Bing`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Match {
+    /// Which list the match came from.
+    pub kind: MatchKind,
+    /// A direct link to the image on the page, if known.
+    pub content_url: Option<String>,
+    /// A link to Bing's thumbnail of the image.
+    pub thumbnail_url: Option<String>,
+    /// The width of the image, if known.
+    pub width: Option<u32>,
+    /// The height of the image, if known.
+    pub height: Option<u32>,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for Bing {
+    type State = ();
+
+    const NAME: SourceId = "bing";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 20 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Check whether we're dealing with an image
+        self.validator.validate(&client, url).await?;
+
+        // Build the request
+
+        let request = json!({ "imageInfo": { "url": url, "source": "Url" } });
+
+        let form = reqwest::multipart::Form::new().text("knowledgeRequest", request.to_string());
+
+        let req = client
+            .post(SEARCH_URL)
+            .query(&[("q", ""), ("iss", "sbi")])
+            .multipart(form);
+
+        let resp = req.send().await?;
+
+        Self::read(url, resp).await
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let form = reqwest::multipart::Form::new().text("imageBin", STANDARD.encode(&data.bytes));
+
+        let req = client
+            .post(SEARCH_URL)
+            .query(&[("q", ""), ("iss", "sbiupload")])
+            .multipart(form);
+
+        let resp = req.send().await?;
+
+        Self::read(&data.origin, resp).await
+    }
+
+    async fn create(_: Self::State) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+}
+
+impl Bing {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    async fn read(original_url: &str, resp: Response) -> Result<Output, Error> {
+        let status = resp.status();
+        let text = resp.text().await?;
+
+        if !status.is_success() {
+            return Err(Error::Generic(format!("Bing returned {status}: {text}")));
+        }
+
+        Self::parse(original_url, &text)
+    }
+
+    /// Parses a response from Bing Visual Search into an [`Output`].
+    ///
+    /// Pages including the image come first, followed by visual matches. A page that shows
+    /// up in both lists is only kept as a [`MatchKind::PageIncluding`].
+    /// Fails if the response isn't the JSON Bing normally sends, such as when it asks for a captcha.
+    fn parse(original_url: &str, text: &str) -> Result<Output, Error> {
+        let json: ApiResponse = serde_json::from_str(text)
+            .map_err(|e| Error::Generic(format!("Bing sent an unexpected response: {e}")))?;
+
+        let mut pages = Vec::new();
+        let mut similar = Vec::new();
+
+        for action in json.tags.into_iter().flat_map(|tag| tag.actions) {
+            let Some(data) = action.data else {
+                continue;
+            };
+
+            let images = data.value.into_iter().filter_map(ApiImage::page);
+
+            match action.action_type.as_str() {
+                "PagesIncluding" => pages.extend(images),
+                "VisualSearch" => similar.extend(images),
+                _ => {}
+            }
+        }
+
+        let mut seen = HashSet::new();
+
+        let items = pages
+            .into_iter()
+            .map(|image| (MatchKind::PageIncluding, image))
+            .chain(
+                similar
+                    .into_iter()
+                    .map(|image| (MatchKind::VisualMatch, image)),
+            )
+            .filter(|(_, (link, _))| seen.insert(link.clone()))
+            .map(|(kind, (link, image))| image.into_item(kind, link))
+            .collect();
+
+        Ok(Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    #[serde(default)]
+    tags: Vec<ApiTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiTag {
+    #[serde(default)]
+    actions: Vec<ApiAction>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiAction {
+    action_type: String,
+    data: Option<ApiData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiData {
+    #[serde(default)]
+    value: Vec<ApiImage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiImage {
+    name: Option<String>,
+    host_page_url: Option<String>,
+    content_url: Option<String>,
+    thumbnail_url: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+impl ApiImage {
+    /// Pairs the image with the page it's on, skipping entries that aren't images, such as related searches.
+    fn page(mut self) -> Option<(String, Self)> {
+        Some((self.host_page_url.take()?, self))
+    }
+
+    fn into_item(self, kind: MatchKind, link: String) -> Item {
+        let site = link
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split('/').next())
+            .map(ToString::to_string);
+
+        Item {
+            title: self.name.filter(|n| !n.is_empty()),
+            site,
+            details: Some(Details::Bing(Match {
+                kind,
+                content_url: self.content_url,
+                thumbnail_url: self.thumbnail_url,
+                width: self.width,
+                height: self.height,
+            })),
+            ..Item::new(link, -1.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bing, MatchKind};
+    use crate::source::Details;
+
+    // A trimmed response from Bing Visual Search, for checking the parsing offline.
+    const FIXTURE: &str = include_str!("../../tests/fixtures/bing.json");
+    const ORIGINAL_URL: &str = "https://example.com/boyfriend.jpg";
+
+    fn kind(details: Option<&Details>) -> Option<MatchKind> {
+        match details {
+            Some(Details::Bing(m)) => Some(m.kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_pages_including() {
+        let output = Bing::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+
+        let first = &output.items[0];
+        assert_eq!(
+            first.link,
+            "https://knowyourmeme.com/memes/distracted-boyfriend"
+        );
+        assert_eq!(
+            first.title.as_deref(),
+            Some("Distracted boyfriend meme - Know Your Meme")
+        );
+        assert_eq!(first.site.as_deref(), Some("knowyourmeme.com"));
+        assert!(first.similarity < 0.0);
+
+        let Some(Details::Bing(m)) = &first.details else {
+            panic!("expected Bing details, got {:?}", first.details);
+        };
+        assert_eq!(m.kind, MatchKind::PageIncluding);
+        assert_eq!(
+            m.content_url.as_deref(),
+            Some("https://i.kym-cdn.com/entries/icons/original/000/023/732/damngina.jpg")
+        );
+        assert_eq!((m.width, m.height), (Some(1200), Some(800)));
+
+        // An empty name is treated as no title
+        let second = &output.items[1];
+        assert_eq!(second.site.as_deref(), Some("en.wikipedia.org"));
+        assert_eq!(second.title, None);
+        assert_eq!(
+            kind(second.details.as_ref()),
+            Some(MatchKind::PageIncluding)
+        );
+    }
+
+    #[test]
+    fn parses_visual_matches_after_pages() {
+        let output = Bing::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+
+        let kinds: Vec<_> = output
+            .items
+            .iter()
+            .map(|item| kind(item.details.as_ref()))
+            .collect();
+
+        // The visual match for Know Your Meme is dropped, as it's already a page including the image
+        assert_eq!(
+            kinds,
+            [
+                Some(MatchKind::PageIncluding),
+                Some(MatchKind::PageIncluding),
+                Some(MatchKind::VisualMatch),
+            ]
+        );
+        assert_eq!(output.items[2].site.as_deref(), Some("www.istockphoto.com"));
+    }
+
+    #[test]
+    fn parses_empty_response() {
+        let output = Bing::parse(ORIGINAL_URL, r#"{"_type":"ImageKnowledge","tags":[]}"#)
+            .expect("empty response should parse");
+
+        assert!(output.items.is_empty());
+    }
+
+    #[test]
+    fn rejects_captcha_page() {
+        let result = Bing::parse(
+            ORIGINAL_URL,
+            "<!DOCTYPE html><html><body>captcha</body></html>",
+        );
+
+        assert!(result.is_err());
+    }
+}
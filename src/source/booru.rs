@@ -0,0 +1,584 @@
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    link, make_client,
+    resolve::BoardLogin,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+/// The [`Booru`] source.
+///
+/// Works with the IQDB endpoints built into Danbooru and e621 (`/iqdb_queries.json`),
+/// including self-hosted Danbooru instances. Each [`Item`] links to the post,
+/// with the full [`Post`] in [`Item::details`].
+#[derive(Debug)]
+pub struct Booru {
+    config: Config,
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+/// Which API a booru speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Danbooru, and instances running its code.
+    Danbooru,
+    /// e621 and e926.
+    E621,
+}
+
+/// The argument for creating a [`Booru`] source.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Which API the booru speaks.
+    pub kind: Kind,
+    /// The base URL of the booru, such as `https://danbooru.donmai.us`.
+    pub base_url: String,
+    /// The login to use, if any. Some instances require one for IQDB queries.
+    pub login: Option<Login>,
+    /// The name to report results under, see [`Source::id`].
+    /// Defaults to `danbooru` or `e621`, after the [`Kind`].
+    pub name: Option<SourceId>,
+}
+
+/// A username and API key for a booru.
+#[derive(Debug, Clone)]
+pub struct Login {
+    /// The username, or login.
+    pub username: String,
+    /// The API key, not the password.
+    pub api_key: String,
+}
+
+impl Config {
+    /// A [`Config`] for `danbooru.donmai.us`, with no login.
+    #[must_use]
+    pub fn danbooru() -> Self {
+        Self {
+            kind: Kind::Danbooru,
+            base_url: "https://danbooru.donmai.us".to_string(),
+            login: None,
+            name: None,
+        }
+    }
+
+    /// A [`Config`] for `e621.net`, with no login.
+    #[must_use]
+    pub fn e621() -> Self {
+        Self {
+            kind: Kind::E621,
+            base_url: "https://e621.net".to_string(),
+            login: None,
+            name: None,
+        }
+    }
+
+    /// Sets the login to use.
+    #[must_use]
+    pub fn login(mut self, username: impl Into<String>, api_key: impl Into<String>) -> Self {
+        self.login = Some(Login {
+            username: username.into(),
+            api_key: api_key.into(),
+        });
+        self
+    }
+
+    /// Sets the name to report results under, such as `"safebooru"` for a second Danbooru instance.
+    #[must_use]
+    pub const fn name(mut self, name: SourceId) -> Self {
+        self.name = Some(name);
+        self
+    }
+}
+
+/// The rating of a [`Post`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    /// Safe for work.
+    General,
+    /// Danbooru's "sensitive", for mildly suggestive posts.
+    Sensitive,
+    /// Questionable.
+    Questionable,
+    /// Explicit.
+    Explicit,
+}
+
+/// A post found by [`Booru`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Post {
+    /// The ID of the post.
+    pub id: u64,
+    /// The MD5 of the file, if it's visible.
+    pub md5: Option<String>,
+    /// The rating of the post.
+    pub rating: Option<Rating>,
+    /// The sources listed on the post, usually links to the artist's page.
+    pub sources: Vec<String>,
+    /// The artist tags.
+    pub artists: Vec<String>,
+    /// Every tag on the post.
+    pub tags: Vec<String>,
+    /// A direct link to the file, if it's visible.
+    pub file_url: Option<String>,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for Booru {
+    type State = Config;
+
+    const NAME: SourceId = "booru";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 10 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Check whether we're dealing with an image
+        self.validator.validate(&client, url).await?;
+
+        // Build the request
+
+        let field = match self.config.kind {
+            Kind::Danbooru => "search[url]",
+            Kind::E621 => "url",
+        };
+
+        let req = self.auth(client.get(self.endpoint()).query(&[(field, url)]));
+
+        let text = self.text(req.send().await?).await?;
+
+        self.parse(url, &text)
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let field = match self.config.kind {
+            Kind::Danbooru => "search[file]",
+            Kind::E621 => "file",
+        };
+
+        let form = reqwest::multipart::Form::new().part(field, data.to_part()?);
+
+        let req = self.auth(client.post(self.endpoint()).multipart(form));
+
+        let text = self.text(req.send().await?).await?;
+
+        self.parse(&data.origin, &text)
+    }
+
+    fn id(&self) -> SourceId {
+        self.config.name.unwrap_or(match self.config.kind {
+            Kind::Danbooru => "danbooru",
+            Kind::E621 => "e621",
+        })
+    }
+
+    // Only Danbooru and e621 themselves are looked up, not self-hosted instances
+    fn board_login(&self) -> Option<BoardLogin> {
+        let login = self.config.login.as_ref()?;
+
+        let site = match (self.config.kind, self.site()?.as_str()) {
+            (Kind::Danbooru, "danbooru.donmai.us") => "danbooru",
+            (Kind::E621, "e621.net") => "e621",
+            _ => return None,
+        };
+
+        Some(BoardLogin {
+            site,
+            username: login.username.clone(),
+            api_key: login.api_key.clone(),
+        })
+    }
+
+    async fn create(config: Self::State) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            validator: Validator::default(),
+        })
+    }
+}
+
+impl Booru {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    fn endpoint(&self) -> String {
+        format!(
+            "{}/iqdb_queries.json",
+            self.config.base_url.trim_end_matches('/')
+        )
+    }
+
+    fn auth(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.config.login {
+            Some(login) => req.basic_auth(&login.username, Some(&login.api_key)),
+            None => req,
+        }
+    }
+
+    /// The name of the site, taken from the host of the base URL.
+    fn site(&self) -> Option<String> {
+        let host = self
+            .config
+            .base_url
+            .split("://")
+            .nth(1)?
+            .split('/')
+            .next()?;
+
+        Some(host.to_string())
+    }
+
+    /// Reads the body of the response, turning it into an [`Error`] if the status isn't a success.
+    async fn text(&self, resp: Response) -> Result<String, Error> {
+        let status = resp.status();
+        let text = resp.text().await?;
+
+        if !status.is_success() {
+            return Err(Error::Generic(format!(
+                "{} returned {status}: {text}",
+                self.config.base_url
+            )));
+        }
+
+        Ok(text)
+    }
+
+    fn parse(&self, original_url: &str, text: &str) -> Result<Output, Error> {
+        let posts: Vec<(f32, Post)> = match self.config.kind {
+            Kind::Danbooru => serde_json::from_str::<Vec<DanbooruMatch>>(text)?
+                .into_iter()
+                .map(|m| (m.score, m.post.into()))
+                .collect(),
+            Kind::E621 => serde_json::from_str::<Vec<E621Match>>(text)?
+                .into_iter()
+                .map(|m| (m.score, m.post.into_post()))
+                .collect(),
+        };
+
+        let site = self.site();
+        let base_url = self.config.base_url.trim_end_matches('/');
+
+        let items = posts
+            .into_iter()
+            .map(|(score, post)| {
+                let item = Item::new(format!("{base_url}/posts/{}", post.id), score);
+
+                Item {
+                    author: (!post.artists.is_empty()).then(|| post.artists.join(", ")),
+                    site: site.clone(),
+                    original_source: link::original_source(&post.sources),
+                    details: Some(Details::Booru(post)),
+                    ..item
+                }
+            })
+            .collect();
+
+        Ok(Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DanbooruMatch {
+    score: f32,
+    post: DanbooruPost,
+}
+
+#[derive(Debug, Deserialize)]
+struct DanbooruPost {
+    id: u64,
+    md5: Option<String>,
+    rating: Option<String>,
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    tag_string: String,
+    #[serde(default)]
+    tag_string_artist: String,
+    file_url: Option<String>,
+}
+
+impl From<DanbooruPost> for Post {
+    fn from(post: DanbooruPost) -> Self {
+        let split = |s: &str| s.split_whitespace().map(ToString::to_string).collect();
+
+        Self {
+            id: post.id,
+            md5: post.md5,
+            rating: post.rating.as_deref().and_then(|r| match r {
+                "g" => Some(Rating::General),
+                "s" => Some(Rating::Sensitive),
+                "q" => Some(Rating::Questionable),
+                "e" => Some(Rating::Explicit),
+                _ => None,
+            }),
+            sources: Some(post.source)
+                .filter(|s| !s.is_empty())
+                .into_iter()
+                .collect(),
+            artists: split(&post.tag_string_artist),
+            tags: split(&post.tag_string),
+            file_url: post.file_url,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct E621Match {
+    score: f32,
+    post: E621PostWrapper,
+}
+
+/// e621 has sent the post both on its own, and wrapped in `{ "posts": ... }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum E621PostWrapper {
+    Wrapped { posts: E621Post },
+    Post(E621Post),
+}
+
+impl E621PostWrapper {
+    fn into_post(self) -> Post {
+        match self {
+            Self::Wrapped { posts } | Self::Post(posts) => posts.into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct E621Post {
+    id: u64,
+    file: E621File,
+    rating: Option<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default)]
+    tags: std::collections::HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct E621File {
+    md5: Option<String>,
+    url: Option<String>,
+}
+
+impl From<E621Post> for Post {
+    fn from(mut post: E621Post) -> Self {
+        let artists = post.tags.remove("artist").unwrap_or_default();
+        let mut tags: Vec<String> = post.tags.into_values().flatten().collect();
+        tags.extend(artists.iter().cloned());
+        tags.sort_unstable();
+
+        Self {
+            id: post.id,
+            md5: post.file.md5,
+            rating: post.rating.as_deref().and_then(|r| match r {
+                "s" => Some(Rating::General),
+                "q" => Some(Rating::Questionable),
+                "e" => Some(Rating::Explicit),
+                _ => None,
+            }),
+            sources: post.sources,
+            artists,
+            tags,
+            file_url: post.file.url,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Booru, Config, Post, Rating};
+    use crate::{source::Details, validate::Validator};
+
+    // Trimmed IQDB query responses from Danbooru and e621, for checking the parsing offline.
+    const DANBOORU: &str = include_str!("../../tests/fixtures/booru_danbooru.json");
+    const E621: &str = include_str!("../../tests/fixtures/booru_e621.json");
+    const ORIGINAL_URL: &str = "https://example.com/fox.png";
+
+    fn booru(config: Config) -> Booru {
+        Booru {
+            config,
+            validator: Validator::default(),
+        }
+    }
+
+    fn posts(booru: &Booru, text: &str) -> Vec<Post> {
+        booru
+            .parse(ORIGINAL_URL, text)
+            .expect("fixture should parse")
+            .items
+            .into_iter()
+            .map(|item| match item.details {
+                Some(Details::Booru(post)) => post,
+                other => panic!("expected booru details, got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_danbooru_matches() {
+        let output = booru(Config::danbooru())
+            .parse(ORIGINAL_URL, DANBOORU)
+            .expect("fixture should parse");
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+
+        let first = &output.items[0];
+        assert_eq!(first.link, "https://danbooru.donmai.us/posts/5123456");
+        assert!((first.similarity - 94.5).abs() < f32::EPSILON);
+        assert_eq!(
+            first.author.as_deref(),
+            Some("kitsune_artist, second_artist")
+        );
+        assert_eq!(first.site.as_deref(), Some("danbooru.donmai.us"));
+        assert_eq!(
+            first.original_source.as_deref(),
+            Some("https://www.pixiv.net/artworks/98765432")
+        );
+
+        // No artist tag and no source leave both empty
+        let second = &output.items[1];
+        assert_eq!(second.author, None);
+        assert_eq!(second.original_source, None);
+    }
+
+    #[test]
+    fn parses_danbooru_posts() {
+        let posts = posts(&booru(Config::danbooru()), DANBOORU);
+
+        let first = &posts[0];
+        assert_eq!(first.id, 5_123_456);
+        assert_eq!(
+            first.md5.as_deref(),
+            Some("0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(
+            first.tags,
+            ["1girl", "fox_ears", "kitsune_artist", "second_artist"]
+        );
+        assert_eq!(first.sources, ["https://www.pixiv.net/artworks/98765432"]);
+
+        // Hidden files have no MD5 or link
+        assert_eq!(posts[1].md5, None);
+        assert_eq!(posts[1].file_url, None);
+        assert!(posts[1].sources.is_empty());
+    }
+
+    #[test]
+    fn maps_danbooru_ratings() {
+        let ratings: Vec<_> = posts(&booru(Config::danbooru()), DANBOORU)
+            .iter()
+            .map(|p| p.rating)
+            .collect();
+
+        assert_eq!(
+            ratings,
+            [
+                Some(Rating::General),
+                Some(Rating::Sensitive),
+                Some(Rating::Questionable),
+                Some(Rating::Explicit),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_wrapped_and_unwrapped_e621_posts() {
+        let output = booru(Config::e621())
+            .parse(ORIGINAL_URL, E621)
+            .expect("fixture should parse");
+
+        let links: Vec<_> = output.items.iter().map(|i| i.link.as_str()).collect();
+        assert_eq!(
+            links,
+            [
+                "https://e621.net/posts/3456789",
+                "https://e621.net/posts/2345678",
+                "https://e621.net/posts/1234567",
+            ]
+        );
+
+        let first = &output.items[0];
+        assert_eq!(first.author.as_deref(), Some("fox_painter"));
+        assert_eq!(
+            first.original_source.as_deref(),
+            Some("https://www.furaffinity.net/view/45678901/")
+        );
+
+        let posts = posts(&booru(Config::e621()), E621);
+        assert_eq!(
+            posts[0].md5.as_deref(),
+            Some("fedcba9876543210fedcba9876543210")
+        );
+        assert_eq!(posts[0].tags, ["fox", "fox_painter", "solo"]);
+        assert_eq!(posts[0].artists, ["fox_painter"]);
+    }
+
+    #[test]
+    fn maps_e621_ratings() {
+        let ratings: Vec<_> = posts(&booru(Config::e621()), E621)
+            .iter()
+            .map(|p| p.rating)
+            .collect();
+
+        assert_eq!(
+            ratings,
+            [
+                Some(Rating::General),
+                Some(Rating::Questionable),
+                Some(Rating::Explicit),
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_the_base_url_of_self_hosted_instances() {
+        let config = Config {
+            base_url: "https://booru.example.com/".to_string(),
+            ..Config::danbooru()
+        };
+
+        let output = booru(config)
+            .parse(ORIGINAL_URL, DANBOORU)
+            .expect("fixture should parse");
+
+        assert_eq!(
+            output.items[0].link,
+            "https://booru.example.com/posts/5123456"
+        );
+        assert_eq!(output.items[0].site.as_deref(), Some("booru.example.com"));
+    }
+
+    #[test]
+    fn rejects_the_wrong_kind() {
+        assert!(booru(Config::danbooru()).parse(ORIGINAL_URL, E621).is_err());
+        assert!(booru(Config::e621()).parse(ORIGINAL_URL, DANBOORU).is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use async_trait::async_trait;
+use reqwest::{StatusCode, header};
+use scraper::{ElementRef, Html};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    error::Error,
+    input::{DownloadOptions, ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+/// The [`EHentai`] source.
+///
+/// Works with the file search on `e-hentai.org`, or `exhentai.org` when [`Config::cookies`] are set.
+/// The file search only accepts uploads, so [`Source::check`] downloads the image first.
+/// Each [`Item`] has the [`Gallery`] in [`Item::details`].
+///
+/// # Notes
+/// E-Hentai doesn't report a similarity, so every [`Item::similarity`] is negative.
+#[derive(Debug)]
+pub struct EHentai {
+    config: Config,
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+/// The argument for creating an [`EHentai`] source.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Cookies for `ExHentai`. Searches go to E-Hentai if these aren't set.
+    pub cookies: Option<Cookies>,
+    /// Whether to look for similar images, rather than only exact copies.
+    ///
+    /// The [`Calibrated`](crate::score::Calibrated) scorer assumes this is on. With it off, every match is a copy
+    /// of the file, so a [`Curve::flat`](crate::score::Curve::flat) of `0.9` for `ehentai` fits better.
+    pub similar: bool,
+    /// Whether to only search the covers of galleries.
+    pub covers_only: bool,
+    /// Whether to include expunged galleries.
+    pub expunged: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            cookies: None,
+            similar: true,
+            covers_only: false,
+            expunged: false,
+        }
+    }
+}
+
+/// The cookies needed to access `ExHentai`, taken from a logged-in browser.
+#[derive(Debug, Clone)]
+pub struct Cookies {
+    /// The `ipb_member_id` cookie.
+    pub ipb_member_id: String,
+    /// The `ipb_pass_hash` cookie.
+    pub ipb_pass_hash: String,
+    /// The `igneous` cookie, if the account has one.
+    pub igneous: Option<String>,
+}
+
+impl Cookies {
+    fn header(&self) -> String {
+        let mut header = format!(
+            "ipb_member_id={}; ipb_pass_hash={}",
+            self.ipb_member_id, self.ipb_pass_hash
+        );
+
+        if let Some(igneous) = &self.igneous {
+            header.push_str("; igneous=");
+            header.push_str(igneous);
+        }
+
+        header
+    }
+}
+
+/// A gallery found by [`EHentai`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Gallery {
+    /// The gallery ID.
+    pub gid: u64,
+    /// The gallery token, needed alongside the ID for the API.
+    pub token: String,
+    /// The title of the gallery.
+    pub title: String,
+    /// The category, such as `Doujinshi` or `Artist CG`.
+    pub category: Option<String>,
+    /// The link to the gallery.
+    pub url: String,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for EHentai {
+    type State = Config;
+
+    const NAME: SourceId = "ehentai";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 10 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // The file search can't fetch links, so download it ourselves
+        let options = DownloadOptions::default()
+            .max_size(Self::UPLOAD_LIMITS.max_bytes)
+            .validator(self.validator.clone());
+
+        let data = ImageData::download(&client, url, &options).await?;
+
+        self.check_data(&data).await
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let mut form = reqwest::multipart::Form::new()
+            .part("sfile", data.to_part()?)
+            .text("f_sfile", "File Search");
+
+        for (field, enabled) in [
+            ("fs_similar", self.config.similar),
+            ("fs_covers", self.config.covers_only),
+            ("fs_exp", self.config.expunged),
+        ] {
+            if enabled {
+                form = form.text(field, "on");
+            }
+        }
+
+        let req = self.config.cookies.as_ref().map_or_else(
+            || client.post("https://upld.e-hentai.org/image_lookup.php"),
+            |cookies| {
+                client
+                    .post("https://exhentai.org/upld/image_lookup.php")
+                    .header(header::COOKIE, cookies.header())
+            },
+        );
+
+        // The upload redirects to the results page
+        let resp = req.multipart(form).send().await?.error_for_status()?;
+
+        let status = resp.status();
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+        let text = resp.text().await?;
+
+        self.check_page(status, content_type.as_deref(), &text)?;
+
+        Ok(Self::parse(&data.origin, &text))
+    }
+
+    async fn create(config: Self::State) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            validator: Validator::default(),
+        })
+    }
+}
+
+impl EHentai {
+    /// Replaces the [`Validator`] used to check downloaded images before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Fails if the answer isn't a results page, such as when the cookies are wrong or the IP is banned.
+    fn check_page(
+        &self,
+        status: StatusCode,
+        content_type: Option<&str>,
+        text: &str,
+    ) -> Result<(), Error> {
+        // ExHentai answers with a blank image when the cookies are wrong
+        if !content_type.is_some_and(|v| v.contains("html")) {
+            return Err(Error::Generic(if self.config.cookies.is_some() {
+                "ExHentai rejected the cookies, check that they're current".to_string()
+            } else {
+                format!("E-Hentai answered with something other than a page ({status})")
+            }));
+        }
+
+        if text.contains("Your IP address has been temporarily banned") {
+            return Err(Error::Generic(
+                "E-Hentai has temporarily banned this IP address".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn parse(original_url: &str, text: &str) -> Output {
+        let html = Html::parse_document(text);
+
+        // The result table differs between display modes, but each row has the gallery link
+        let items: Vec<Item> = html
+            .select(sel!("table.itg > tbody > tr, div.itg > div.gl1t"))
+            .filter_map(Self::harvest_gallery)
+            .collect();
+
+        Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        }
+    }
+
+    fn harvest_gallery(row: ElementRef) -> Option<Item> {
+        debug!("selecting gallery link");
+        let link = row.select(sel!("a[href*='/g/']")).next()?;
+        let url = link.value().attr("href")?.to_string();
+
+        debug!("parsing gid and token");
+        let mut parts = url.split("/g/").nth(1)?.split('/');
+        let gid = parts.next()?.parse::<u64>().ok()?;
+        let token = parts.next()?.to_string();
+
+        debug!("grabbing title");
+        let title = row
+            .select(sel!(".glink"))
+            .next()
+            .map_or_else(|| link.text().collect(), |t| t.text().collect::<String>());
+        let title = title.trim().to_string();
+
+        debug!("grabbing category");
+        let category = row
+            .select(sel!(".cn, .cs"))
+            .next()
+            .map(|c| c.text().collect::<String>().trim().to_string());
+
+        let site = if url.contains("exhentai.org") {
+            "ExHentai"
+        } else {
+            "E-Hentai"
+        };
+
+        Some(Item {
+            title: Some(title.clone()).filter(|t| !t.is_empty()),
+            site: Some(site.to_string()),
+            details: Some(Details::EHentai(Gallery {
+                gid,
+                token,
+                title,
+                category,
+                url: url.clone(),
+            })),
+            ..Item::new(url, -1.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::{Config, Cookies, EHentai, Gallery};
+    use crate::{error::Error, source::Details, validate::Validator};
+
+    // Trimmed file search results from E-Hentai in the compact and thumbnail display modes,
+    // for checking the parsing offline.
+    const COMPACT: &str = include_str!("../../tests/fixtures/ehentai.html");
+    const THUMBNAILS: &str = include_str!("../../tests/fixtures/ehentai_thumbnails.html");
+    const ORIGINAL_URL: &str = "https://example.com/page.jpg";
+
+    fn ehentai(cookies: Option<Cookies>) -> EHentai {
+        EHentai {
+            config: Config {
+                cookies,
+                ..Config::default()
+            },
+            validator: Validator::default(),
+        }
+    }
+
+    fn cookies(igneous: Option<&str>) -> Cookies {
+        Cookies {
+            ipb_member_id: "12345".to_string(),
+            ipb_pass_hash: "0123abcd".to_string(),
+            igneous: igneous.map(ToString::to_string),
+        }
+    }
+
+    fn galleries(text: &str) -> Vec<Gallery> {
+        EHentai::parse(ORIGINAL_URL, text)
+            .items
+            .into_iter()
+            .map(|item| match item.details {
+                Some(Details::EHentai(gallery)) => gallery,
+                other => panic!("expected E-Hentai details, got {other:?}"),
+            })
+            .collect()
+    }
+
+    fn message(res: Result<(), Error>) -> String {
+        match res {
+            Err(Error::Generic(message)) => message,
+            other => panic!("expected a generic error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_compact_results() {
+        let output = EHentai::parse(ORIGINAL_URL, COMPACT);
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+        assert_eq!(output.items.len(), 2);
+
+        let first = &output.items[0];
+        assert_eq!(first.link, "https://e-hentai.org/g/2345678/abcdef1234/");
+        assert_eq!(
+            first.title.as_deref(),
+            Some("[Circle (Artist)] Summer Story")
+        );
+        assert_eq!(first.site.as_deref(), Some("E-Hentai"));
+        assert!(first.similarity < 0.0);
+
+        let gallery = &galleries(COMPACT)[0];
+        assert_eq!(gallery.gid, 2_345_678);
+        assert_eq!(gallery.token, "abcdef1234");
+        assert_eq!(gallery.category.as_deref(), Some("Doujinshi"));
+
+        assert_eq!(galleries(COMPACT)[1].category.as_deref(), Some("Artist CG"));
+    }
+
+    #[test]
+    fn parses_thumbnail_results() {
+        let output = EHentai::parse(ORIGINAL_URL, THUMBNAILS);
+
+        let links: Vec<_> = output.items.iter().map(|i| i.link.as_str()).collect();
+        assert_eq!(
+            links,
+            [
+                "https://exhentai.org/g/3456789/0987fedcba/",
+                "https://exhentai.org/g/3456790/1111aaaa22/",
+            ]
+        );
+        assert_eq!(output.items[0].site.as_deref(), Some("ExHentai"));
+        assert_eq!(output.items[0].title.as_deref(), Some("Winter Story"));
+
+        let gallery = &galleries(THUMBNAILS)[1];
+        assert_eq!(gallery.gid, 3_456_790);
+        assert_eq!(gallery.category.as_deref(), Some("Non-H"));
+    }
+
+    #[test]
+    fn parses_page_without_results() {
+        let output = EHentai::parse(
+            ORIGINAL_URL,
+            "<html><body><p>No hits found</p></body></html>",
+        );
+
+        assert!(output.items.is_empty());
+    }
+
+    #[test]
+    fn accepts_results_pages() {
+        let res =
+            ehentai(None).check_page(StatusCode::OK, Some("text/html; charset=UTF-8"), COMPACT);
+
+        assert!(res.is_ok(), "{res:?}");
+    }
+
+    #[test]
+    fn blames_the_cookies_only_when_set() {
+        let with = ehentai(Some(cookies(None))).check_page(StatusCode::OK, Some("image/gif"), "");
+        assert!(message(with).contains("cookies"));
+
+        let without = ehentai(None).check_page(StatusCode::OK, None, "");
+        let without = message(without);
+        assert!(!without.contains("cookies"), "{without}");
+        assert!(without.contains("200"), "{without}");
+    }
+
+    #[test]
+    fn reports_ip_bans() {
+        let page = "<html><body>Your IP address has been temporarily banned for excessive pageloads.</body></html>";
+
+        let res = ehentai(None).check_page(StatusCode::OK, Some("text/html"), page);
+
+        assert!(message(res).contains("banned"));
+    }
+
+    #[test]
+    fn builds_the_cookie_header() {
+        assert_eq!(
+            cookies(None).header(),
+            "ipb_member_id=12345; ipb_pass_hash=0123abcd"
+        );
+        assert_eq!(
+            cookies(Some("f00")).header(),
+            "ipb_member_id=12345; ipb_pass_hash=0123abcd; igneous=f00"
+        );
+    }
+}
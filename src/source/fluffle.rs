@@ -0,0 +1,305 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    input::{DownloadOptions, ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+const SEARCH_URL: &str = "https://api.fluffle.xyz/v1/search";
+
+/// The [`Fluffle`] source.
+///
+/// Works with `fluffle.xyz`, which covers Inkbunny, Weasyl, `FurryNetwork`, Twitter and more.
+/// Fluffle only accepts uploads, so [`Source::check`] downloads the image first.
+/// Each [`Item`] has the [`Match`] in [`Item::details`].
+#[derive(Debug)]
+pub struct Fluffle {
+    config: Config,
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+/// The argument for creating a [`Fluffle`] source.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Whether to include NSFW results.
+    pub include_nsfw: bool,
+    /// How many results to return. Fluffle's default is used if unset.
+    pub limit: Option<u32>,
+}
+
+/// How confident Fluffle is that a result is the searched image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchQuality {
+    /// The result is the same image.
+    Exact,
+    /// The result is probably the same image, but it's a toss-up.
+    TossUp,
+    /// The result is an alternative version, such as a sketch or an edit.
+    Alternative,
+    /// The result is unlikely to be the same image.
+    Unlikely,
+}
+
+/// A match found by [`Fluffle`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Match {
+    /// Fluffle's ID for the result.
+    pub id: u64,
+    /// The platform the result is from, such as `furAffinity`.
+    pub platform: String,
+    /// How confident Fluffle is in the result.
+    pub quality: MatchQuality,
+    /// The artists credited for the result.
+    pub artists: Vec<String>,
+    /// Whether the result is safe for work.
+    pub is_sfw: bool,
+    /// A link to a thumbnail of the result.
+    pub thumbnail: Option<String>,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for Fluffle {
+    type State = Config;
+
+    const NAME: SourceId = "fluffle";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 4 * 1024 * 1024,
+        max_width: 4096,
+        max_height: 4096,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Fluffle can't fetch links, so download it ourselves
+        let options = DownloadOptions::default()
+            .max_size(Self::UPLOAD_LIMITS.max_bytes)
+            .validator(self.validator.clone());
+
+        let data = ImageData::download(&client, url, &options).await?;
+
+        self.check_data(&data).await
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", data.to_part()?)
+            .text("includeNsfw", self.config.include_nsfw.to_string());
+
+        if let Some(limit) = self.config.limit {
+            form = form.text("limit", limit.to_string());
+        }
+
+        let resp = client.post(SEARCH_URL).multipart(form).send().await?;
+
+        // Parse the response
+
+        let status = resp.status();
+        let text = resp.text().await?;
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<ApiError>(&text)
+                .map(|e| e.message)
+                .unwrap_or(text);
+
+            return Err(Error::Generic(format!(
+                "Fluffle returned {status}: {message}"
+            )));
+        }
+
+        Self::parse(&data.origin, &text)
+    }
+
+    async fn create(config: Self::State) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            validator: Validator::default(),
+        })
+    }
+}
+
+impl Fluffle {
+    /// Replaces the [`Validator`] used to check downloaded images before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    fn parse(original_url: &str, text: &str) -> Result<Output, Error> {
+        let json: ApiResponse = serde_json::from_str(text)?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let items = json
+            .results
+            .into_iter()
+            .map(|result| Item {
+                author: (!result.credits.is_empty()).then(|| {
+                    result
+                        .credits
+                        .iter()
+                        .map(|c| c.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }),
+                site: Some(result.platform.clone()),
+                details: Some(Details::Fluffle(Match {
+                    id: result.id,
+                    platform: result.platform,
+                    quality: result.quality,
+                    artists: result.credits.into_iter().map(|c| c.name).collect(),
+                    is_sfw: result.is_sfw,
+                    thumbnail: result.thumbnail.map(|t| t.location),
+                })),
+                ..Item::new(result.location, (result.score * 100.0) as f32)
+            })
+            .collect();
+
+        Ok(Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    results: Vec<ApiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiResult {
+    id: u64,
+    score: f64,
+    #[serde(rename = "match")]
+    quality: MatchQuality,
+    platform: String,
+    location: String,
+    #[serde(default = "default_sfw")]
+    is_sfw: bool,
+    thumbnail: Option<ApiThumbnail>,
+    #[serde(default)]
+    credits: Vec<ApiCredit>,
+}
+
+const fn default_sfw() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiThumbnail {
+    location: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCredit {
+    name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fluffle, Match, MatchQuality};
+    use crate::source::Details;
+
+    // A trimmed search response from Fluffle, for checking the parsing offline.
+    const FIXTURE: &str = include_str!("../../tests/fixtures/fluffle.json");
+    const ORIGINAL_URL: &str = "https://example.com/wolf.png";
+
+    fn matches(text: &str) -> Vec<Match> {
+        Fluffle::parse(ORIGINAL_URL, text)
+            .expect("fixture should parse")
+            .items
+            .into_iter()
+            .map(|item| match item.details {
+                Some(Details::Fluffle(m)) => m,
+                other => panic!("expected Fluffle details, got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_results() {
+        let output = Fluffle::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+
+        let first = &output.items[0];
+        assert_eq!(first.link, "https://www.furaffinity.net/view/41234567");
+        assert!(
+            (first.similarity - 97.3).abs() < 0.01,
+            "{}",
+            first.similarity
+        );
+        assert_eq!(first.author.as_deref(), Some("grey_wolf, colourist"));
+        assert_eq!(first.site.as_deref(), Some("furAffinity"));
+
+        let m = &matches(FIXTURE)[0];
+        assert_eq!(m.id, 41_234_567);
+        assert_eq!(m.artists, ["grey_wolf", "colourist"]);
+        assert!(!m.is_sfw);
+        assert_eq!(
+            m.thumbnail.as_deref(),
+            Some("https://static.fluffle.xyz/thumbnails/abc.jpg")
+        );
+    }
+
+    #[test]
+    fn parses_every_match_quality() {
+        let qualities: Vec<_> = matches(FIXTURE).iter().map(|m| m.quality).collect();
+
+        assert_eq!(
+            qualities,
+            [
+                MatchQuality::Exact,
+                MatchQuality::TossUp,
+                MatchQuality::Alternative,
+                MatchQuality::Unlikely,
+            ]
+        );
+    }
+
+    #[test]
+    fn fills_in_missing_fields() {
+        let output = Fluffle::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+        let last = &matches(FIXTURE)[3];
+
+        // No credits, thumbnail or SFW flag
+        assert_eq!(output.items[3].author, None);
+        assert!(last.artists.is_empty());
+        assert_eq!(last.thumbnail, None);
+        assert!(last.is_sfw);
+    }
+
+    #[test]
+    fn rejects_unknown_match_quality() {
+        let text = r#"{"results":[{"id":1,"score":0.5,"match":"maybe","platform":"twitter","location":"https://twitter.com/a/status/1"}]}"#;
+
+        assert!(Fluffle::parse(ORIGINAL_URL, text).is_err());
+    }
+}
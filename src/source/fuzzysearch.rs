@@ -1,56 +1,42 @@
 use std::fmt::Debug;
 
 use async_trait::async_trait;
-use reqwest::{StatusCode, header};
+use reqwest::StatusCode;
 use tracing::{debug, warn};
 
-use crate::{error::Error, make_client};
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    link, make_client,
+    validate::{ImageFormat, Validator},
+};
 
-use super::{Item, Output, Source};
+use super::{Item, Output, Source, SourceId};
 
 #[allow(dead_code)]
 mod _internal;
-use _internal::{FuzzySearch as FuzzySearchInternal, FuzzySearchOpts};
+use _internal::{File, FuzzySearch as FuzzySearchInternal, FuzzySearchOpts, MatchType, SiteInfo};
 
 /// The [`FuzzySearch`] source.
 ///
 /// Works with `https://fuzzysearch.net`
 pub struct FuzzySearch {
     internal: FuzzySearchInternal,
+    /// Checks links before they are sent off.
+    validator: Validator,
 }
 
-impl Debug for FuzzySearch {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("FuzzySearch").finish()
+impl FuzzySearch {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
     }
-}
-
-#[async_trait]
-#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-impl Source for FuzzySearch {
-    type State = String;
-
-    async fn check(&self, url: &str) -> Result<Output, Error> {
-        let client = make_client();
-
-        // Check whether we're dealing with an image
-        let head = client.head(url).send().await?;
-
-        let content_type = head.headers().get(header::CONTENT_TYPE);
-
-        if let Some(content_type) = content_type {
-            let content_type = content_type.to_str()?;
-
-            if !content_type.contains("image") {
-                return Err(Error::LinkIsNotImage);
-            }
-        } else {
-            return Err(Error::LinkIsNotImage);
-        }
-
-        // Build the request
-        let resp = self.internal.lookup_url(url).await;
 
+    /// Converts the response from the API into an [`Output`], mapping status codes to errors.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn into_output(original_url: &str, resp: reqwest::Result<Vec<File>>) -> Result<Output, Error> {
         // Check the status
         if let Err(e) = resp {
             // let status = e.status().expect("A status code should be present");
@@ -82,16 +68,22 @@ impl Source for FuzzySearch {
         // Convert the response to the output format
 
         let mut output = Output {
-            original_url: url.to_string(),
+            original_url: original_url.to_string(),
             items: Vec::new(),
+            crop: None,
         };
 
         for result in results {
             let distance = result.distance.unwrap_or(0);
 
+            let original_source = match &result.site_info {
+                Some(SiteInfo::E621(file)) => link::original_source(file.sources.iter().flatten()),
+                _ => None,
+            };
+
             let item = Item {
-                link: result.url(),
-                similarity: 100f32 / ((distance + 1) * 100) as f32,
+                original_source,
+                ..Item::new(result.url(), 100f32 / ((distance + 1) * 100) as f32)
             };
 
             output.items.push(item);
@@ -103,6 +95,56 @@ impl Source for FuzzySearch {
 
         Ok(output)
     }
+}
+
+impl Debug for FuzzySearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FuzzySearch")
+            .field("validator", &self.validator)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for FuzzySearch {
+    type State = String;
+
+    const NAME: SourceId = "fuzzysearch";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 10 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Check whether we're dealing with an image
+        self.validator.validate(&client, url).await?;
+
+        // Build the request
+        let resp = self.internal.lookup_url(url).await;
+
+        Self::into_output(url, resp)
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        // Build the request
+        let resp = self
+            .internal
+            .image_search(&data.bytes, MatchType::Close, None)
+            .await;
+
+        Self::into_output(&data.origin, resp)
+    }
 
     async fn create(state: Self::State) -> Result<Self, Error> {
         Ok(Self {
@@ -111,6 +153,7 @@ impl Source for FuzzySearch {
                 client: Some(make_client()),
                 endpoint: Some("https://api.fuzzysearch.net".to_string()),
             }),
+            validator: Validator::default(),
         })
     }
 }
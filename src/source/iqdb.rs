@@ -1,61 +1,98 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::Duration;
 
 use async_trait::async_trait;
-use reqwest::header;
 use scraper::ElementRef;
 use tracing::debug;
 
-use crate::{error::Error, make_client};
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
 
-use super::{Item, Output, Source};
+use super::{Item, Output, Source, SourceId};
 
 /// The [`IQDB`] source.
 ///
 /// Works with `iqdb.org`
-#[derive(Debug)]
-pub struct Iqdb;
-
-/// A macro that creates a &Selector from a string literal.
-macro_rules! sel {
-    ($sel:literal) => {
-        &scraper::Selector::parse($sel).expect("invalid selector")
-    };
+#[derive(Debug, Default)]
+pub struct Iqdb {
+    /// Checks links before they are sent off.
+    validator: Validator,
 }
 
-#[async_trait]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl Source for Iqdb {
     type State = ();
 
+    const NAME: SourceId = "iqdb";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 8 * 1024 * 1024,
+        max_width: 7500,
+        max_height: 7500,
+        formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif],
+    };
+
     async fn check(&self, url: &str) -> Result<Output, Error> {
         let client = make_client();
 
         // Check whether we're dealing with an image
-        let head = client.head(url).send().await?;
+        self.validator.validate(&client, url).await?;
 
-        let content_type = head.headers().get(header::CONTENT_TYPE);
+        // Build the request
 
-        if let Some(content_type) = content_type {
-            let content_type = content_type.to_str()?;
+        let req = client.get("https://iqdb.org/").query(&[("url", url)]);
 
-            if !content_type.contains("image") {
-                return Err(Error::LinkIsNotImage);
-            }
-        } else {
-            return Err(Error::LinkIsNotImage);
-        }
+        // The browser decides when a request has taken too long
+        #[cfg(not(target_arch = "wasm32"))]
+        let req = req.timeout(Duration::from_secs(10));
+
+        let resp = req.send().await?;
+
+        let text = resp.text().await?;
+
+        Ok(Self::parse(url, &text))
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
 
         // Build the request
 
-        let req = client
-            .get("https://iqdb.org/")
-            .query(&[("url", url)])
-            .timeout(Duration::from_secs(10));
+        let form = reqwest::multipart::Form::new().part("file", data.to_part()?);
+
+        let req = client.post("https://iqdb.org/").multipart(form);
+
+        // The browser decides when a request has taken too long
+        #[cfg(not(target_arch = "wasm32"))]
+        let req = req.timeout(Duration::from_secs(10));
 
         let resp = req.send().await?;
 
         let text = resp.text().await?;
 
-        let html = scraper::Html::parse_document(&text);
+        Ok(Self::parse(&data.origin, &text))
+    }
+
+    async fn create(_: Self::State) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+}
+
+impl Iqdb {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    fn parse(original_url: &str, text: &str) -> Output {
+        let html = scraper::Html::parse_document(text);
 
         let items: Vec<Item> = html
             .select(sel!("#pages > div"))
@@ -63,18 +100,13 @@ impl Source for Iqdb {
             .filter_map(Self::harvest_page)
             .collect();
 
-        Ok(Output {
-            original_url: url.to_string(),
+        Output {
+            original_url: original_url.to_string(),
             items,
-        })
-    }
-
-    async fn create(_: Self::State) -> Result<Self, Error> {
-        Ok(Self)
+            crop: None,
+        }
     }
-}
 
-impl Iqdb {
     fn harvest_page(page: ElementRef) -> Option<Item> {
         debug!("selecting .image a");
         let link = page.select(sel!(".image a")).next()?;
@@ -95,9 +127,6 @@ impl Iqdb {
         let score = score.text().collect::<String>();
         let score = score.split_once('%')?.0.parse::<f32>().ok()? / 100.0;
 
-        Some(Item {
-            link: url,
-            similarity: score,
-        })
+        Some(Item::new(url, score))
     }
 }
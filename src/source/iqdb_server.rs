@@ -0,0 +1,294 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    input::{DownloadOptions, ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+/// The [`IqdbServer`] source.
+///
+/// Works with a self-hosted iqdb server, such as the one Danbooru runs, through its
+/// `POST /query` endpoint. Unlike [`Iqdb`](super::iqdb::Iqdb), nothing is scraped, so it can
+/// search a private archive as easily as a public booru.
+/// The server only accepts uploads, so [`Source::check`] downloads the image first.
+/// Each [`Item`] has the [`Post`] in [`Item::details`].
+#[derive(Debug)]
+pub struct IqdbServer {
+    config: Config,
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+/// The argument for creating an [`IqdbServer`] source.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The base URL of the server, such as `http://localhost:5588`.
+    pub base_url: String,
+    /// The link for each post, with `{id}` replaced by the post ID.
+    /// Links to the server's own `/images/{id}` if unset.
+    pub post_url: Option<String>,
+    /// How many results to return. The server's default is used if unset.
+    pub limit: Option<u32>,
+    /// The name to report results under, see [`Source::id`]. Defaults to `iqdb_server`.
+    pub name: Option<SourceId>,
+}
+
+impl Config {
+    /// A [`Config`] for the server at `base_url`, linking to the server itself.
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            post_url: None,
+            limit: None,
+            name: None,
+        }
+    }
+
+    /// Sets the link for each post, with `{id}` replaced by the post ID,
+    /// such as `https://archive.example/posts/{id}`.
+    #[must_use]
+    pub fn post_url(mut self, post_url: impl Into<String>) -> Self {
+        self.post_url = Some(post_url.into());
+        self
+    }
+
+    /// Sets how many results to return.
+    #[must_use]
+    pub const fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Sets the name to report results under, to tell several servers apart.
+    #[must_use]
+    pub const fn name(mut self, name: SourceId) -> Self {
+        self.name = Some(name);
+        self
+    }
+}
+
+/// A post found by [`IqdbServer`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Post {
+    /// The ID the post was added to the server with.
+    pub id: u64,
+    /// The score the server gave the post, usually from `0.0` to `100.0`.
+    pub score: f32,
+    /// The server's hash of the post's image, if it sent one.
+    pub hash: Option<String>,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for IqdbServer {
+    type State = Config;
+
+    const NAME: SourceId = "iqdb_server";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 10 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // The server can't fetch links, so download it ourselves
+        let options = DownloadOptions::default()
+            .max_size(Self::UPLOAD_LIMITS.max_bytes)
+            .validator(self.validator.clone());
+
+        let data = ImageData::download(&client, url, &options).await?;
+
+        self.check_data(&data).await
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let form = reqwest::multipart::Form::new().part("file", data.to_part()?);
+
+        let mut req = client
+            .post(format!("{}/query", self.base_url()))
+            .multipart(form);
+
+        if let Some(limit) = self.config.limit {
+            req = req.query(&[("limit", limit)]);
+        }
+
+        let resp = req.send().await?;
+
+        // Parse the response
+
+        let status = resp.status();
+        let text = resp.text().await?;
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<ApiError>(&text)
+                .map(|e| e.message)
+                .unwrap_or(text);
+
+            return Err(Error::Generic(format!(
+                "{} returned {status}: {message}",
+                self.config.base_url
+            )));
+        }
+
+        self.parse(&data.origin, &text)
+    }
+
+    fn id(&self) -> SourceId {
+        self.config.name.unwrap_or(Self::NAME)
+    }
+
+    async fn create(config: Self::State) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            validator: Validator::default(),
+        })
+    }
+}
+
+impl IqdbServer {
+    /// Replaces the [`Validator`] used to check downloaded images before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    fn base_url(&self) -> &str {
+        self.config.base_url.trim_end_matches('/')
+    }
+
+    fn parse(&self, original_url: &str, text: &str) -> Result<Output, Error> {
+        let json: Vec<ApiMatch> = serde_json::from_str(text)?;
+
+        let items = json
+            .into_iter()
+            .map(|m| Item {
+                details: Some(Details::IqdbServer(Post {
+                    id: m.post_id,
+                    score: m.score,
+                    hash: m.hash,
+                })),
+                ..Item::new(self.link(m.post_id), m.score)
+            })
+            .collect();
+
+        Ok(Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        })
+    }
+
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn link(&self, id: u64) -> String {
+        self.config.post_url.as_ref().map_or_else(
+            || format!("{}/images/{id}", self.base_url()),
+            |template| template.replace("{id}", &id.to_string()),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMatch {
+    post_id: u64,
+    score: f32,
+    hash: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, IqdbServer};
+    use crate::{source::Details, validate::Validator};
+
+    // A query response from an iqdb server, for checking the parsing offline.
+    const FIXTURE: &str = include_str!("../../tests/fixtures/iqdb_server.json");
+    const ORIGINAL_URL: &str = "https://example.com/scan.jpg";
+
+    fn server(config: Config) -> IqdbServer {
+        IqdbServer {
+            config,
+            validator: Validator::default(),
+        }
+    }
+
+    #[test]
+    fn parses_matches() {
+        let output = server(Config::new("http://localhost:5588/"))
+            .parse(ORIGINAL_URL, FIXTURE)
+            .expect("fixture should parse");
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+
+        let links: Vec<_> = output.items.iter().map(|i| i.link.as_str()).collect();
+        assert_eq!(
+            links,
+            [
+                "http://localhost:5588/images/1201",
+                "http://localhost:5588/images/88",
+            ]
+        );
+
+        let first = &output.items[0];
+        assert!((first.similarity - 96.82).abs() < f32::EPSILON);
+
+        let Some(Details::IqdbServer(post)) = &first.details else {
+            panic!("expected iqdb server details, got {:?}", first.details);
+        };
+        assert_eq!(post.id, 1201);
+        assert_eq!(post.hash.as_deref(), Some("iqdb_3fe4c95b4d9a2e1f"));
+
+        // Older servers don't send the hash
+        let Some(Details::IqdbServer(post)) = &output.items[1].details else {
+            panic!("expected iqdb server details");
+        };
+        assert_eq!(post.hash, None);
+    }
+
+    #[test]
+    fn links_with_the_post_url_template() {
+        let config =
+            Config::new("http://localhost:5588").post_url("https://archive.example/posts/{id}");
+
+        let output = server(config)
+            .parse(ORIGINAL_URL, FIXTURE)
+            .expect("fixture should parse");
+
+        assert_eq!(output.items[0].link, "https://archive.example/posts/1201");
+    }
+
+    #[test]
+    fn parses_empty_response() {
+        let output = server(Config::new("http://localhost:5588"))
+            .parse(ORIGINAL_URL, "[]")
+            .expect("empty response should parse");
+
+        assert!(output.items.is_empty());
+    }
+
+    #[test]
+    fn rejects_error_bodies() {
+        let result = server(Config::new("http://localhost:5588"))
+            .parse(ORIGINAL_URL, r#"{"message":"no file given"}"#);
+
+        assert!(result.is_err());
+    }
+}
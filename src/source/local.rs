@@ -0,0 +1,782 @@
+use std::{
+    f64::consts::PI,
+    fmt, fs,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use async_trait::async_trait;
+use image::{DynamicImage, ImageError, imageops::FilterType};
+use serde::Serialize;
+
+use crate::{
+    error::Error,
+    input::{DEFAULT_MAX_SIZE, DownloadOptions, ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+/// Identifies an index file, followed by [`VERSION`].
+const MAGIC: &[u8; 8] = b"SAUCEIDX";
+
+/// The version of the index file format.
+const VERSION: u32 = 1;
+
+/// Counts the saves made by this process, to give each its own temporary file.
+static SAVES: AtomicU64 = AtomicU64::new(0);
+
+/// The [`LocalIndex`] source.
+///
+/// Searches an [`Index`] of perceptual hashes kept on this machine, so it makes no requests
+/// beyond downloading the image, and has no quota. Useful as a first tier in front of the
+/// online sources, for an archive whose sources are already known.
+/// Each [`Item`] has the links stored in the [`Entry`], with the [`Match`] in [`Item::details`].
+#[derive(Debug)]
+pub struct LocalIndex {
+    config: Config,
+    /// Checks links before they are downloaded.
+    validator: Validator,
+}
+
+/// The argument for creating a [`LocalIndex`] source.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The index to search.
+    pub index: Index,
+    /// The largest number of differing bits, out of 64, for a hash to count as a match.
+    pub max_distance: u32,
+}
+
+impl Config {
+    /// A [`Config`] for `index`, allowing up to 10 differing bits.
+    #[must_use]
+    pub const fn new(index: Index) -> Self {
+        Self {
+            index,
+            max_distance: 10,
+        }
+    }
+
+    /// Sets the largest number of differing bits for a hash to count as a match.
+    #[must_use]
+    pub const fn max_distance(mut self, max_distance: u32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+}
+
+/// An image stored in an [`Index`], with what's known about where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The perceptual hash of the image, see [`hash`].
+    pub hash: u64,
+    /// The link to the original source.
+    pub link: String,
+    /// The title of the work, if known.
+    pub title: Option<String>,
+    /// The author or artist of the work, if known.
+    pub author: Option<String>,
+    /// The name of the site the link points to, if known.
+    pub site: Option<String>,
+}
+
+impl Entry {
+    /// An [`Entry`] with only a hash and a link.
+    #[must_use]
+    pub fn new(hash: u64, link: impl Into<String>) -> Self {
+        Self {
+            hash,
+            link: link.into(),
+            title: None,
+            author: None,
+            site: None,
+        }
+    }
+
+    /// Sets the title.
+    #[must_use]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Sets the author.
+    #[must_use]
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Sets the site.
+    #[must_use]
+    pub fn site(mut self, site: impl Into<String>) -> Self {
+        self.site = Some(site.into());
+        self
+    }
+}
+
+/// A match found by [`LocalIndex`].
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Match {
+    /// The hash stored in the index.
+    pub hash: u64,
+    /// How many bits differ from the hash of the searched image.
+    pub distance: u32,
+}
+
+/// A BK-tree of [`Entry`]s, keyed by the Hamming distance between their hashes.
+///
+/// Saved to disk as the entries in insertion order, which rebuilds the same tree when loaded.
+#[derive(Clone, Default)]
+pub struct Index {
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone)]
+struct Node {
+    entry: Entry,
+    /// The distance to each child, and its position in [`Index::nodes`].
+    children: Vec<(u32, usize)>,
+}
+
+impl fmt::Debug for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Index")
+            .field("len", &self.nodes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Index {
+    /// An empty [`Index`].
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    /// How many entries are in the index.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the index has no entries.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Iterates over the entries, in insertion order.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.nodes.iter().map(|node| &node.entry)
+    }
+
+    /// Adds an entry. Entries with the same hash are all kept.
+    pub fn insert(&mut self, entry: Entry) {
+        let new = self.nodes.len();
+
+        if new == 0 {
+            self.nodes.push(Node {
+                entry,
+                children: Vec::new(),
+            });
+            return;
+        }
+
+        let mut current = 0;
+
+        loop {
+            let distance = (self.nodes[current].entry.hash ^ entry.hash).count_ones();
+
+            let next = self.nodes[current]
+                .children
+                .iter()
+                .find(|(d, _)| *d == distance)
+                .map(|(_, child)| *child);
+
+            if let Some(child) = next {
+                current = child;
+                continue;
+            }
+
+            self.nodes[current].children.push((distance, new));
+            self.nodes.push(Node {
+                entry,
+                children: Vec::new(),
+            });
+            return;
+        }
+    }
+
+    /// Hashes an image and adds it with the given link.
+    ///
+    /// # Errors
+    /// - [`Error::UnsupportedFormat`] if the image can't be decoded, such as AVIF
+    pub fn add(&mut self, data: &ImageData, link: impl Into<String>) -> Result<(), Error> {
+        self.insert(Entry::new(hash(data)?, link));
+
+        Ok(())
+    }
+
+    /// Finds every entry within `max_distance` bits of `hash`, closest first.
+    #[must_use]
+    pub fn find(&self, hash: u64, max_distance: u32) -> Vec<(u32, &Entry)> {
+        let mut found = Vec::new();
+
+        if self.nodes.is_empty() {
+            return found;
+        }
+
+        let mut stack = vec![0];
+
+        while let Some(current) = stack.pop() {
+            let node = &self.nodes[current];
+            let distance = (node.entry.hash ^ hash).count_ones();
+
+            if distance <= max_distance {
+                found.push((distance, &node.entry));
+            }
+
+            // Only children within `max_distance` of `distance` can hold a match
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| d.abs_diff(distance) <= max_distance)
+                    .map(|(_, child)| *child),
+            );
+        }
+
+        found.sort_by_key(|(distance, _)| *distance);
+        found
+    }
+
+    /// Loads an index saved with [`Index::save`].
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the file can't be read, or isn't an index
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+
+        Self::read_from(BufReader::new(file))
+    }
+
+    /// Saves the index to `path`, replacing it only once the whole index is written.
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the file can't be written
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        // Next to the index, so the rename stays on one filesystem, and named for this save
+        // alone, so saves running at the same time never write into each other's file
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(
+            ".{}-{}.tmp",
+            process::id(),
+            SAVES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp = PathBuf::from(temp);
+
+        let written = self
+            .write_file(&temp)
+            .and_then(|()| Ok(fs::rename(&temp, path)?));
+
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+
+        written
+    }
+
+    fn write_file(&self, path: &Path) -> Result<(), Error> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?
+            .sync_all()?;
+
+        Ok(())
+    }
+
+    /// Reads an index written by [`Index::write_to`].
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if reading fails, or the data isn't an index
+    pub fn read_from(mut reader: impl Read) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(Error::Generic("Not a sauce-api index".to_string()));
+        }
+
+        let version = read_u32(&mut reader)?;
+
+        if version != VERSION {
+            return Err(Error::Generic(format!(
+                "Unsupported index version {version}, expected {VERSION}"
+            )));
+        }
+
+        let count = read_u64(&mut reader)?;
+        let mut index = Self::new();
+
+        for _ in 0..count {
+            let hash = read_u64(&mut reader)?;
+            let link = read_string(&mut reader)?;
+
+            index.insert(Entry {
+                hash,
+                link,
+                title: read_optional(&mut reader)?,
+                author: read_optional(&mut reader)?,
+                site: read_optional(&mut reader)?,
+            });
+        }
+
+        Ok(index)
+    }
+
+    /// Writes the index in the format read by [`Index::read_from`].
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if writing fails
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+
+        for entry in self.entries() {
+            writer.write_all(&entry.hash.to_le_bytes())?;
+            write_string(&mut writer, &entry.link)?;
+            write_optional(&mut writer, entry.title.as_deref())?;
+            write_optional(&mut writer, entry.author.as_deref())?;
+            write_optional(&mut writer, entry.site.as_deref())?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Computes the 64-bit perceptual hash of an image, for storing in an [`Index`].
+///
+/// # Errors
+/// - [`Error::UnsupportedFormat`] if the image can't be decoded, such as AVIF
+pub fn hash(data: &ImageData) -> Result<u64, Error> {
+    let image = image::load_from_memory(&data.bytes).map_err(|e| match e {
+        ImageError::Unsupported(_) => Error::UnsupportedFormat(data.format),
+        e => Error::Generic(e.to_string()),
+    })?;
+
+    Ok(hash_image(&image))
+}
+
+/// Computes the 64-bit perceptual hash of an image that's already decoded.
+///
+/// The image is shrunk to 32x32 in greyscale, and each bit says whether one of the lowest
+/// 8x8 frequencies of its cosine transform is above the median. Resizing, recompression and
+/// small edits only flip a few bits.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub fn hash_image(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+
+    let small = image
+        .resize_exact(SIZE as u32, SIZE as u32, FilterType::Triangle)
+        .to_luma8();
+
+    let pixels: Vec<f64> = small.pixels().map(|p| f64::from(p.0[0])).collect();
+
+    let cosines: Vec<f64> = (0..LOW * SIZE)
+        .map(|i| {
+            let (u, x) = (i / SIZE, i % SIZE);
+            ((2 * x + 1) as f64 * u as f64 * PI / (2 * SIZE) as f64).cos()
+        })
+        .collect();
+
+    // The transform is separable, so do the rows, then the columns
+    let mut rows = [[0.0; LOW]; SIZE];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = (0..SIZE)
+                .map(|x| pixels[y * SIZE + x] * cosines[u * SIZE + x])
+                .sum();
+        }
+    }
+
+    let mut coefficients = [0.0; LOW * LOW];
+    for (i, value) in coefficients.iter_mut().enumerate() {
+        let (v, u) = (i / LOW, i % LOW);
+        *value = (0..SIZE).map(|y| rows[y][u] * cosines[v * SIZE + y]).sum();
+    }
+
+    // The first coefficient is the average brightness, which would skew the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| **value > median)
+        .fold(0, |hash, (i, _)| hash | (1 << i))
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for LocalIndex {
+    type State = Config;
+
+    const NAME: SourceId = "local";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: DEFAULT_MAX_SIZE,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // The image has to be hashed here, so download it
+        let options = DownloadOptions::default()
+            .max_size(Self::UPLOAD_LIMITS.max_bytes)
+            .validator(self.validator.clone());
+
+        let data = ImageData::download(&client, url, &options).await?;
+
+        self.check_data(&data).await
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let hash = hash_blocking(data).await?;
+
+        #[allow(clippy::cast_precision_loss)]
+        let items = self
+            .config
+            .index
+            .find(hash, self.config.max_distance)
+            .into_iter()
+            .map(|(distance, entry)| Item {
+                title: entry.title.clone(),
+                author: entry.author.clone(),
+                site: entry.site.clone(),
+                details: Some(Details::Local(Match {
+                    hash: entry.hash,
+                    distance,
+                })),
+                ..Item::new(entry.link.clone(), (64 - distance) as f32 / 64.0 * 100.0)
+            })
+            .collect();
+
+        Ok(Output {
+            original_url: data.origin.clone(),
+            items,
+            crop: None,
+        })
+    }
+
+    async fn create(config: Self::State) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            validator: Validator::default(),
+        })
+    }
+}
+
+impl LocalIndex {
+    /// Replaces the [`Validator`] used to check downloaded images before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// The index being searched.
+    #[must_use]
+    pub const fn index(&self) -> &Index {
+        &self.config.index
+    }
+}
+
+/// Runs [`hash`] on tokio's blocking threads when on a tokio runtime, as decoding a large
+/// image would otherwise hold up the executor. Hashes in place everywhere else.
+#[allow(clippy::unused_async)] // Only awaits outside the browser
+async fn hash_blocking(data: &ImageData) -> Result<u64, Error> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        let data = data.clone();
+
+        return tokio::task::spawn_blocking(move || hash(&data))
+            .await
+            .map_err(|e| Error::Generic(format!("Hashing the image failed: {e}")))?;
+    }
+
+    hash(data)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, Error> {
+    let len = read_u32(reader)?;
+
+    let mut bytes = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len as usize {
+        return Err(Error::Generic("Index ended early".to_string()));
+    }
+
+    String::from_utf8(bytes).map_err(|e| Error::Generic(e.to_string()))
+}
+
+fn read_optional(reader: &mut impl Read) -> Result<Option<String>, Error> {
+    let mut present = [0; 1];
+    reader.read_exact(&mut present)?;
+
+    match present[0] {
+        0 => Ok(None),
+        _ => read_string(reader).map(Some),
+    }
+}
+
+fn write_string(writer: &mut impl Write, s: &str) -> Result<(), Error> {
+    let len = u32::try_from(s.len())
+        .map_err(|_| Error::Generic("String too long for an index".to_string()))?;
+
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(s.as_bytes())?;
+
+    Ok(())
+}
+
+fn write_optional(writer: &mut impl Write, s: Option<&str>) -> Result<(), Error> {
+    match s {
+        Some(s) => {
+            writer.write_all(&[1])?;
+            write_string(writer, s)
+        }
+        None => Ok(writer.write_all(&[0])?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat as Encoding, RgbImage, imageops::FilterType};
+
+    use super::{Config, Entry, Index, hash_image};
+    use crate::input::ImageData;
+
+    /// Hashes spread over the whole range, from a fixed seed.
+    fn hashes(count: usize) -> Vec<u64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            })
+            .collect()
+    }
+
+    /// A picture with enough structure for its hash to survive resizing.
+    #[allow(clippy::cast_possible_truncation)]
+    fn picture(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x * 256 / width, y * 256 / height);
+            let circle = x.abs_diff(90).pow(2) + y.abs_diff(150).pow(2) < 60 * 60;
+
+            let shade = if circle { 255 - y as u8 } else { x as u8 };
+
+            image::Rgb([shade, ((x ^ y) / 4) as u8, 128])
+        }))
+    }
+
+    fn encode(image: &DynamicImage) -> ImageData {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), Encoding::Png)
+            .expect("encoding should work");
+
+        ImageData::from_bytes("test.png", bytes).expect("should be a PNG")
+    }
+
+    #[test]
+    fn write_to_and_read_from_round_trip() {
+        let mut index = Index::new();
+        index.insert(
+            Entry::new(1, "https://example.com/1")
+                .title("Title")
+                .author("Author")
+                .site("example.com"),
+        );
+        index.insert(Entry::new(1, "https://example.com/duplicate"));
+        index.insert(Entry::new(u64::MAX, "https://example.com/ünïcode").site(""));
+
+        let mut bytes = Vec::new();
+        index.write_to(&mut bytes).expect("writing should work");
+
+        let read = Index::read_from(bytes.as_slice()).expect("reading should work");
+
+        assert!(read.entries().eq(index.entries()));
+        assert_eq!(read.find(1, 0).len(), 2);
+    }
+
+    #[test]
+    fn save_leaves_files_with_the_same_stem_alone() {
+        let dir = std::env::temp_dir().join(format!("sauce-local-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("creating the directory should work");
+
+        let mut index = Index::new();
+        index.insert(Entry::new(1, "https://example.com/1"));
+
+        let other = dir.join("archive.tmp");
+        std::fs::write(&other, "not an index").expect("writing should work");
+
+        index
+            .save(dir.join("archive.idx"))
+            .expect("saving should work");
+
+        let loaded = Index::load(dir.join("archive.idx")).expect("loading should work");
+        let untouched = std::fs::read_to_string(&other).expect("reading should work");
+
+        std::fs::remove_dir_all(&dir).expect("cleaning up should work");
+
+        assert!(loaded.entries().eq(index.entries()));
+        assert_eq!(untouched, "not an index");
+    }
+
+    #[test]
+    fn concurrent_saves_never_leave_a_broken_index() {
+        let dir = std::env::temp_dir().join(format!("sauce-local-saves-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("creating the directory should work");
+        let path = dir.join("archive.idx");
+
+        let indexes: Vec<Index> = (1..=8_u64)
+            .map(|n| {
+                let mut index = Index::new();
+                for hash in 0..n * 500 {
+                    index.insert(Entry::new(hash, format!("https://example.com/{hash}")));
+                }
+                index
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            for index in &indexes {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..4 {
+                        index.save(path).expect("saving should work");
+                    }
+                });
+            }
+        });
+
+        let loaded = Index::load(&path).expect("loading should work");
+        let leftovers = std::fs::read_dir(&dir)
+            .expect("listing should work")
+            .count();
+
+        std::fs::remove_dir_all(&dir).expect("cleaning up should work");
+
+        assert!(indexes.iter().any(|i| loaded.entries().eq(i.entries())));
+        assert_eq!(leftovers, 1, "temporary files were left behind");
+    }
+
+    #[test]
+    fn read_from_rejects_other_files() {
+        assert!(Index::read_from(b"PNG\r\n\x1a\n\0\0\0\x01".as_slice()).is_err());
+
+        // Cut off partway through an entry
+        let mut bytes = Vec::new();
+        let mut index = Index::new();
+        index.insert(Entry::new(1, "https://example.com/1"));
+        index.write_to(&mut bytes).expect("writing should work");
+
+        assert!(Index::read_from(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn find_returns_exactly_the_entries_within_the_distance() {
+        let hashes = hashes(500);
+
+        let mut index = Index::new();
+        for (i, hash) in hashes.iter().enumerate() {
+            index.insert(Entry::new(*hash, i.to_string()));
+        }
+
+        for target in hashes.iter().step_by(50) {
+            for max_distance in [0, 20, 28, 32] {
+                let mut found: Vec<_> = index
+                    .find(*target, max_distance)
+                    .into_iter()
+                    .map(|(distance, entry)| (distance, entry.link.clone()))
+                    .collect();
+
+                let mut expected: Vec<_> = hashes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hash)| ((hash ^ target).count_ones(), i.to_string()))
+                    .filter(|(distance, _)| *distance <= max_distance)
+                    .collect();
+
+                assert!(found.is_sorted_by_key(|(distance, _)| *distance));
+
+                found.sort();
+                expected.sort();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn resized_copies_are_within_the_default_distance() {
+        let original = picture(512, 384);
+        let hash = super::hash(&encode(&original)).expect("PNGs can be hashed");
+
+        let max_distance = Config::new(Index::new()).max_distance;
+
+        for (width, height) in [(256, 192), (200, 150), (1024, 768)] {
+            let resized = original.resize_exact(width, height, FilterType::Lanczos3);
+
+            assert!(
+                (hash ^ hash_image(&resized)).count_ones() <= max_distance,
+                "{width}x{height} is too far"
+            );
+        }
+
+        let other = picture(512, 384).fliph();
+        assert!((hash ^ hash_image(&other)).count_ones() > max_distance);
+    }
+}
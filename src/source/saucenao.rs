@@ -2,9 +2,14 @@ use async_trait::async_trait;
 use reqwest::header;
 use serde::{Deserialize, Serialize};
 
-use crate::{error::Error, make_client};
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    link, make_client,
+    validate::{ImageFormat, Validator},
+};
 
-use super::{Item, Output, Source};
+use super::{Item, Output, Source, SourceId};
 
 /// The [`SauceNao`] source.
 /// Requires an API key to function.
@@ -14,29 +19,71 @@ use super::{Item, Output, Source};
 pub struct SauceNao {
     /// The API key to use.
     api_key: String,
+    /// Checks links before they are sent off.
+    validator: Validator,
 }
 
-#[async_trait]
+impl SauceNao {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Parses the JSON response into an [`Output`].
+    fn parse(original_url: &str, text: &str) -> Result<Output, Error> {
+        let json: ApiResponse = serde_json::from_str(text)?;
+
+        let mut result = Output {
+            original_url: original_url.to_string(),
+            items: Vec::new(),
+            crop: None,
+        };
+
+        for item in json.results {
+            if let Some(links) = item.data.ext_urls {
+                let item = Item {
+                    original_source: item
+                        .data
+                        .source
+                        .as_deref()
+                        .and_then(|s| link::original_source([s])),
+                    ..Item::new(links[0].clone(), item.header.similarity.parse::<f32>()?)
+                };
+
+                result.items.push(item);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl Source for SauceNao {
     type State = String;
 
+    const NAME: SourceId = "saucenao";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 15 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
     async fn check(&self, url: &str) -> Result<Output, Error> {
         let client = make_client();
 
         // Check whether we're dealing with an image
-        let head = client.head(url).send().await?;
-
-        let content_type = head.headers().get(header::CONTENT_TYPE);
-
-        if let Some(content_type) = content_type {
-            let content_type = content_type.to_str()?;
-
-            if !content_type.contains("image") {
-                return Err(Error::LinkIsNotImage);
-            }
-        } else {
-            return Err(Error::LinkIsNotImage);
-        }
+        self.validator.validate(&client, url).await?;
 
         // Build the request
 
@@ -54,35 +101,48 @@ impl Source for SauceNao {
         // Parse the response
 
         let text = resp.text().await?;
-        let json: ApiResponse = serde_json::from_str(&text)?;
 
-        let mut result = Output {
-            original_url: url.to_string(),
-            items: Vec::new(),
+        Self::parse(url, &text)
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let form = reqwest::multipart::Form::new().part("file", data.to_part()?);
+
+        let req = {
+            client
+                .post("https://saucenao.com/search.php")
+                .query(&Query::default().api_key(&self.api_key))
+                .header(header::ACCEPT_ENCODING, "utf-8")
+                .multipart(form)
         };
 
-        for item in json.results {
-            if let Some(links) = item.data.ext_urls {
-                let item = Item {
-                    similarity: item.header.similarity.parse::<f32>()?,
-                    link: links[0].clone(),
-                };
+        // Send the request
 
-                result.items.push(item);
-            }
-        }
+        let resp = req.send().await?;
 
-        Ok(result)
+        // Parse the response
+
+        let text = resp.text().await?;
+
+        Self::parse(&data.origin, &text)
     }
 
     async fn create(arg: Self::State) -> Result<Self, Error> {
-        Ok(Self { api_key: arg })
+        Ok(Self {
+            api_key: arg,
+            validator: Validator::default(),
+        })
     }
 }
 
 #[derive(Debug, Serialize)]
 struct Query {
-    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
     api_key: String,
     db: u16,
     output_type: u8,
@@ -94,7 +154,7 @@ struct Query {
 
 impl Query {
     pub fn url(mut self, url: &str) -> Self {
-        self.url = url.to_string();
+        self.url = Some(url.to_string());
         self
     }
 
@@ -107,7 +167,7 @@ impl Query {
 impl Default for Query {
     fn default() -> Self {
         Self {
-            url: String::new(),
+            url: None,
             api_key: String::new(),
             db: 999,
             output_type: 2,
@@ -136,4 +196,6 @@ struct ApiItemHeader {
 #[derive(Debug, Deserialize)]
 struct ApiItemData {
     ext_urls: Option<Vec<String>>,
+    /// Where a booru post came from, or the title of an anime or manga.
+    source: Option<String>,
 }
@@ -0,0 +1,428 @@
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Details, Item, Output, Source, SourceId};
+
+const BASE_URL: &str = "https://api.trace.moe";
+
+/// The [`TraceMoe`] source.
+/// An API key is optional, but raises the quota.
+///
+/// Works with `trace.moe`. Each [`Item`] links to the anime on `AniList`, with the
+/// matching [`Scene`] in [`Item::details`].
+#[derive(Debug)]
+pub struct TraceMoe {
+    config: Config,
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+/// The argument for creating a [`TraceMoe`] source.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The API key to use, if any.
+    pub api_key: Option<String>,
+    /// Whether trace.moe should cut off black borders before searching.
+    pub cut_borders: bool,
+}
+
+/// A scene found by [`TraceMoe`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Scene {
+    /// The ID of the anime on `AniList`.
+    pub anilist_id: u64,
+    /// The ID of the anime on `MyAnimeList`, if known.
+    pub mal_id: Option<u64>,
+    /// The title of the anime, preferring English, then romaji, then native.
+    pub title: Option<String>,
+    /// Whether the anime is marked as adult.
+    pub is_adult: bool,
+    /// The episode, if known. Usually a number, but can be something like `"OVA"` or `"1-2"`.
+    pub episode: Option<String>,
+    /// The name of the video file the scene was found in.
+    pub filename: String,
+    /// When the scene starts, in seconds.
+    pub from: f64,
+    /// When the scene ends, in seconds.
+    pub to: f64,
+    /// How similar the scene is, from `0.0` to `1.0`.
+    pub similarity: f64,
+    /// A short preview video of the scene.
+    pub video: String,
+    /// A preview image of the scene.
+    pub image: String,
+}
+
+/// The quota for the current API key, or IP address if there is none.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quota {
+    /// The API key's user ID, or the IP address.
+    pub id: String,
+    /// The priority of searches.
+    pub priority: u32,
+    /// How many searches can run at once.
+    pub concurrency: u32,
+    /// How many searches can be made this month.
+    #[serde(rename = "quota")]
+    pub limit: u64,
+    /// How many searches have been made this month.
+    #[serde(rename = "quotaUsed")]
+    pub used: u64,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for TraceMoe {
+    type State = Config;
+
+    const NAME: SourceId = "tracemoe";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 25 * 1024 * 1024,
+        max_width: 10_000,
+        max_height: 10_000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Check whether we're dealing with an image
+        self.validator.validate(&client, url).await?;
+
+        // Build the request
+
+        let req = self.search(
+            client
+                .get(format!("{BASE_URL}/search"))
+                .query(&[("url", url)]),
+        );
+
+        let text = Self::text(req.send().await?).await?;
+
+        Self::parse(url, &text)
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let form = reqwest::multipart::Form::new().part("image", data.to_part()?);
+
+        let req = self.search(client.post(format!("{BASE_URL}/search")).multipart(form));
+
+        let text = Self::text(req.send().await?).await?;
+
+        Self::parse(&data.origin, &text)
+    }
+
+    async fn create(config: Self::State) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            validator: Validator::default(),
+        })
+    }
+}
+
+impl TraceMoe {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Gets the search quota for the API key, or for the current IP address if there is none.
+    ///
+    /// # Errors
+    /// - [`Error::Generic`] if the request fails, or trace.moe reports an error
+    pub async fn me(&self) -> Result<Quota, Error> {
+        let client = make_client();
+
+        let resp = self
+            .auth(client.get(format!("{BASE_URL}/me")))
+            .send()
+            .await?;
+
+        let text = Self::text(resp).await?;
+
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn auth(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.config.api_key {
+            Some(key) => req.header("x-trace-key", key),
+            None => req,
+        }
+    }
+
+    fn search(&self, req: RequestBuilder) -> RequestBuilder {
+        let req = self.auth(req).query(&[("anilistInfo", "")]);
+
+        if self.config.cut_borders {
+            req.query(&[("cutBorders", "")])
+        } else {
+            req
+        }
+    }
+
+    /// Reads the body of the response, turning the `error` field into an [`Error`] if the status isn't a success.
+    async fn text(resp: Response) -> Result<String, Error> {
+        let status = resp.status();
+        let text = resp.text().await?;
+
+        if status.is_success() {
+            return Ok(text);
+        }
+
+        let error = serde_json::from_str::<ApiError>(&text)
+            .map(|e| e.error)
+            .unwrap_or(text);
+
+        Err(Error::Generic(format!(
+            "trace.moe returned {status}: {error}"
+        )))
+    }
+
+    fn parse(original_url: &str, text: &str) -> Result<Output, Error> {
+        let json: ApiResponse = serde_json::from_str(text)?;
+
+        if !json.error.is_empty() {
+            return Err(Error::Generic(json.error));
+        }
+
+        let items = json
+            .result
+            .into_iter()
+            .map(|result| {
+                let scene = result.into_scene();
+
+                #[allow(clippy::cast_possible_truncation)]
+                let item = Item::new(
+                    format!("https://anilist.co/anime/{}", scene.anilist_id),
+                    (scene.similarity * 100.0) as f32,
+                );
+
+                Item {
+                    title: scene.title.clone(),
+                    site: Some("AniList".to_string()),
+                    details: Some(Details::TraceMoe(scene)),
+                    ..item
+                }
+            })
+            .collect();
+
+        Ok(Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    result: Vec<ApiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResult {
+    anilist: ApiAnilist,
+    filename: String,
+    /// Can be a number, a string, an array of numbers, or null.
+    episode: Value,
+    from: f64,
+    to: f64,
+    similarity: f64,
+    video: String,
+    image: String,
+}
+
+/// Just the ID without `anilistInfo`, or the full info with it.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ApiAnilist {
+    Id(u64),
+    Info(ApiAnilistInfo),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiAnilistInfo {
+    id: u64,
+    id_mal: Option<u64>,
+    title: ApiTitle,
+    #[serde(default)]
+    is_adult: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiTitle {
+    native: Option<String>,
+    romaji: Option<String>,
+    english: Option<String>,
+}
+
+impl ApiResult {
+    fn into_scene(self) -> Scene {
+        let (anilist_id, mal_id, title, is_adult) = match self.anilist {
+            ApiAnilist::Id(id) => (id, None, None, false),
+            ApiAnilist::Info(info) => (
+                info.id,
+                info.id_mal,
+                info.title
+                    .english
+                    .or(info.title.romaji)
+                    .or(info.title.native),
+                info.is_adult,
+            ),
+        };
+
+        let episode = match self.episode {
+            Value::Null => None,
+            Value::String(s) => Some(s),
+            Value::Array(a) => Some(
+                a.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("-"),
+            ),
+            v => Some(v.to_string()),
+        };
+
+        Scene {
+            anilist_id,
+            mal_id,
+            title,
+            is_adult,
+            episode,
+            filename: self.filename,
+            from: self.from,
+            to: self.to,
+            similarity: self.similarity,
+            video: self.video,
+            image: self.image,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Quota, TraceMoe};
+    use crate::source::Details;
+
+    // A trimmed search response from trace.moe with `anilistInfo`, for checking the parsing offline.
+    const FIXTURE: &str = include_str!("../../tests/fixtures/tracemoe.json");
+    const ORIGINAL_URL: &str = "https://example.com/mob.jpg";
+
+    #[test]
+    fn parses_scenes() {
+        let output = TraceMoe::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+        assert_eq!(output.items.len(), 4);
+
+        let first = &output.items[0];
+        assert_eq!(first.link, "https://anilist.co/anime/21034");
+        assert!(
+            (first.similarity - 96.41).abs() < 0.01,
+            "{}",
+            first.similarity
+        );
+        assert_eq!(first.site.as_deref(), Some("AniList"));
+
+        let Some(Details::TraceMoe(scene)) = &first.details else {
+            panic!("expected trace.moe details, got {:?}", first.details);
+        };
+        assert_eq!(scene.anilist_id, 21034);
+        assert_eq!(scene.mal_id, Some(32182));
+        assert_eq!(scene.episode.as_deref(), Some("3"));
+        assert!((scene.from - 663.17).abs() < f64::EPSILON);
+        assert!(!scene.is_adult);
+    }
+
+    #[test]
+    fn falls_back_from_english_to_romaji_to_native_titles() {
+        let output = TraceMoe::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+
+        let titles: Vec<_> = output.items.iter().map(|i| i.title.as_deref()).collect();
+        assert_eq!(
+            titles,
+            [
+                Some("Mob Psycho 100"),
+                Some("モブサイコ100 REIGEN"),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_every_episode_shape() {
+        let output = TraceMoe::parse(ORIGINAL_URL, FIXTURE).expect("fixture should parse");
+
+        let episodes: Vec<_> = output
+            .items
+            .iter()
+            .map(|item| match &item.details {
+                Some(Details::TraceMoe(scene)) => scene.episode.clone(),
+                other => panic!("expected trace.moe details, got {other:?}"),
+            })
+            .collect();
+
+        assert_eq!(
+            episodes,
+            [
+                Some("3".to_string()),
+                Some("1-2".to_string()),
+                None,
+                Some("OVA".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_in_the_body() {
+        let result = TraceMoe::parse(
+            ORIGINAL_URL,
+            r#"{"error":"Search queue is full","result":[]}"#,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parses_quota() {
+        let quota: Quota = serde_json::from_str(
+            r#"{"id":"127.0.0.1","priority":0,"concurrency":1,"quota":1000,"quotaUsed":43}"#,
+        )
+        .expect("quota should parse");
+
+        assert_eq!(quota.id, "127.0.0.1");
+        assert_eq!((quota.limit, quota.used), (1000, 43));
+    }
+}
@@ -0,0 +1,271 @@
+use async_trait::async_trait;
+use reqwest::Response;
+use scraper::{ElementRef, Html};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{
+    error::Error,
+    input::{ImageData, UploadLimits},
+    make_client,
+    validate::{ImageFormat, Validator},
+};
+
+use super::{Item, Output, Source, SourceId};
+
+const SEARCH_URL: &str = "https://yandex.com/images/search";
+
+/// The [`Yandex`] source.
+///
+/// Works with `yandex.com`, returning the "sites containing this image" list.
+///
+/// # Notes
+/// Yandex doesn't report a similarity, so every [`Item::similarity`] is negative.
+/// Yandex is also quick to ask for a captcha, which is returned as an [`Error::Generic`].
+#[derive(Debug, Default)]
+pub struct Yandex {
+    /// Checks links before they are sent off.
+    validator: Validator,
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Source for Yandex {
+    type State = ();
+
+    const NAME: SourceId = "yandex";
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_bytes: 8 * 1024 * 1024,
+        max_width: 8000,
+        max_height: 8000,
+        formats: &[
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ],
+    };
+
+    async fn check(&self, url: &str) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Check whether we're dealing with an image
+        self.validator.validate(&client, url).await?;
+
+        // Build the request
+
+        let req = client
+            .get(SEARCH_URL)
+            .query(&[("rpt", "imageview"), ("url", url)]);
+
+        let text = Self::read(req.send().await?).await?;
+
+        Self::parse(url, &text)
+    }
+
+    async fn check_data(&self, data: &ImageData) -> Result<Output, Error> {
+        let client = make_client();
+
+        // Build the request
+
+        let form = reqwest::multipart::Form::new().part("upfile", data.to_part()?);
+
+        // Yandex redirects to the results page once the upload is done
+        let req = client
+            .post(SEARCH_URL)
+            .query(&[("rpt", "imageview")])
+            .multipart(form);
+
+        let text = Self::read(req.send().await?).await?;
+
+        Self::parse(&data.origin, &text)
+    }
+
+    async fn create(_: Self::State) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+}
+
+impl Yandex {
+    /// Replaces the [`Validator`] used to check links before searching.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Reads the results page, failing if Yandex redirected to a captcha instead.
+    async fn read(resp: Response) -> Result<String, Error> {
+        if resp.url().path().contains("showcaptcha") {
+            return Err(Self::captcha());
+        }
+
+        Ok(resp.error_for_status()?.text().await?)
+    }
+
+    fn captcha() -> Error {
+        Error::Generic("Yandex asked for a captcha".to_string())
+    }
+
+    fn parse(original_url: &str, text: &str) -> Result<Output, Error> {
+        let html = Html::parse_document(text);
+
+        // The captcha is sometimes served in place of the results, without a redirect
+        if html
+            .select(sel!("form[action*=checkcaptcha]"))
+            .next()
+            .is_some()
+        {
+            return Err(Self::captcha());
+        }
+
+        let mut items: Vec<Item> = html
+            .select(sel!(".CbirSites-Item"))
+            .filter_map(Self::harvest_site)
+            .collect();
+
+        // Newer layouts only ship the results as JSON, for the page's scripts to render
+        if items.is_empty() {
+            debug!("no .CbirSites-Item, looking for data-state");
+
+            items = html
+                .select(sel!(".cbir-section [data-state]"))
+                .filter_map(|e| e.value().attr("data-state"))
+                .filter_map(|state| serde_json::from_str::<DataState>(state).ok())
+                .flat_map(|state| state.sites)
+                .map(Into::into)
+                .collect();
+        }
+
+        Ok(Output {
+            original_url: original_url.to_string(),
+            items,
+            crop: None,
+        })
+    }
+
+    fn harvest_site(site: ElementRef) -> Option<Item> {
+        debug!("selecting .CbirSites-ItemTitle a");
+        let title = site.select(sel!(".CbirSites-ItemTitle a")).next()?;
+
+        debug!("grabbing href");
+        let url = title.value().attr("href")?;
+        let url = if url.starts_with("//") {
+            format!("https:{url}")
+        } else {
+            url.to_string()
+        };
+
+        debug!("grabbing domain");
+        let domain = site
+            .select(sel!(".CbirSites-ItemDomain"))
+            .next()
+            .map(|d| d.text().collect::<String>().trim().to_string());
+
+        Some(Item {
+            title: Some(title.text().collect::<String>().trim().to_string())
+                .filter(|t| !t.is_empty()),
+            site: domain.filter(|d| !d.is_empty()),
+            ..Item::new(url, -1.0)
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DataState {
+    sites: Vec<DataSite>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataSite {
+    url: String,
+    title: Option<String>,
+    domain: Option<String>,
+}
+
+impl From<DataSite> for Item {
+    fn from(site: DataSite) -> Self {
+        Self {
+            title: site.title,
+            site: site.domain,
+            ..Self::new(site.url, -1.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Yandex;
+
+    // Trimmed results pages from Yandex, for checking the parsing offline.
+    // The older layout renders the sites as HTML, the newer one only as JSON in `data-state`.
+    const SITES: &str = include_str!("../../tests/fixtures/yandex.html");
+    const DATA_STATE: &str = include_str!("../../tests/fixtures/yandex_state.html");
+    const CAPTCHA: &str = include_str!("../../tests/fixtures/yandex_captcha.html");
+    const ORIGINAL_URL: &str = "https://example.com/cat.jpg";
+
+    #[test]
+    fn parses_sites_list() {
+        let output = Yandex::parse(ORIGINAL_URL, SITES).expect("fixture should parse");
+
+        assert_eq!(output.original_url, ORIGINAL_URL);
+
+        let links: Vec<_> = output.items.iter().map(|i| i.link.as_str()).collect();
+        assert_eq!(
+            links,
+            [
+                "https://www.pixiv.net/artworks/91234567",
+                "https://www.reddit.com/r/cats/comments/abc123/loaf/",
+            ]
+        );
+        assert!(output.items.iter().all(|i| i.similarity < 0.0));
+
+        let first = &output.items[0];
+        assert_eq!(first.title.as_deref(), Some("Loaf - pixiv"));
+        assert_eq!(first.site.as_deref(), Some("pixiv.net"));
+        assert!(first.site_ref.is_some());
+
+        // Protocol-relative links are made absolute, and a blank title is left empty
+        let second = &output.items[1];
+        assert_eq!(second.title, None);
+        assert_eq!(second.site.as_deref(), Some("reddit.com"));
+    }
+
+    #[test]
+    fn falls_back_to_data_state() {
+        let output = Yandex::parse(ORIGINAL_URL, DATA_STATE).expect("fixture should parse");
+
+        let links: Vec<_> = output.items.iter().map(|i| i.link.as_str()).collect();
+        assert_eq!(
+            links,
+            [
+                "https://danbooru.donmai.us/posts/4567890",
+                "https://www.tumblr.com/catloaves/700000000000000000",
+            ]
+        );
+
+        let first = &output.items[0];
+        assert_eq!(first.title.as_deref(), Some("loaf cat - Danbooru"));
+        assert_eq!(first.site.as_deref(), Some("danbooru.donmai.us"));
+        assert_eq!(output.items[1].title, None);
+    }
+
+    #[test]
+    fn parses_page_without_results() {
+        let output = Yandex::parse(
+            ORIGINAL_URL,
+            "<html><body><div class=\"cbir-section\"></div></body></html>",
+        )
+        .expect("empty page should parse");
+
+        assert!(output.items.is_empty());
+    }
+
+    #[test]
+    fn rejects_captcha_page() {
+        let result = Yandex::parse(ORIGINAL_URL, CAPTCHA);
+
+        assert!(result.is_err());
+    }
+}
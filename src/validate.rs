@@ -0,0 +1,315 @@
+use std::fmt;
+
+use reqwest::{Client, header};
+use tracing::debug;
+
+use crate::{error::Error, input::read_body};
+
+/// How many bytes are needed to tell the supported formats apart.
+const SNIFF_LEN: usize = 32;
+
+/// The image formats that can be recognised by the [`Validator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ImageFormat {
+    /// `image/jpeg`
+    Jpeg,
+    /// `image/png`
+    Png,
+    /// `image/gif`
+    Gif,
+    /// `image/webp`
+    WebP,
+    /// `image/avif`
+    Avif,
+}
+
+impl ImageFormat {
+    /// All formats known to the crate.
+    pub const ALL: &'static [Self] = &[Self::Jpeg, Self::Png, Self::Gif, Self::WebP, Self::Avif];
+
+    /// The MIME type of the format.
+    #[must_use]
+    pub const fn mime(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Gif => "image/gif",
+            Self::WebP => "image/webp",
+            Self::Avif => "image/avif",
+        }
+    }
+
+    /// The usual file extension of the format, without the leading dot.
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Gif => "gif",
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    /// Parses a `Content-Type` header value, ignoring any parameters such as `charset`.
+    #[must_use]
+    pub fn from_mime(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim();
+
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|f| f.mime().eq_ignore_ascii_case(mime))
+            .or_else(|| mime.eq_ignore_ascii_case("image/jpg").then_some(Self::Jpeg))
+    }
+
+    /// Recognises a format from the first few bytes of a file.
+    #[must_use]
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Self::Png),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, rest @ ..] if rest.starts_with(b"WEBP") => {
+                Some(Self::WebP)
+            }
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] if Self::is_avif_ftyp(bytes) => {
+                Some(Self::Avif)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks the major and compatible brands of an ISO-BMFF `ftyp` box for AVIF.
+    fn is_avif_ftyp(bytes: &[u8]) -> bool {
+        ftyp_brands(bytes).any(|brand| brand == b"avif" || brand == b"avis")
+    }
+}
+
+/// The major and compatible brands of the ISO-BMFF `ftyp` box at the start of `bytes`.
+///
+/// Empty if the box is cut short before the major brand.
+pub(crate) fn ftyp_brands(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let size = bytes.get(..4).map_or(0, |size| {
+        u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize
+    });
+    let end = size.min(bytes.len());
+
+    // The major brand sits at 8..12, the minor version at 12..16,
+    // and the compatible brands follow in groups of four.
+    bytes
+        .get(8..12)
+        .into_iter()
+        .chain(bytes.get(16..end).unwrap_or_default().chunks_exact(4))
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mime())
+    }
+}
+
+/// Checks that a link leads to an image before it is handed to a source.
+///
+/// A `HEAD` request is tried first. If the server rejects it, or answers with a
+/// `Content-Type` that isn't a known image type (such as `application/octet-stream`),
+/// the first few bytes are fetched with a ranged `GET` and the format is sniffed from them.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    allowed: Vec<ImageFormat>,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self {
+            allowed: ImageFormat::ALL.to_vec(),
+        }
+    }
+}
+
+impl Validator {
+    /// Creates a [`Validator`] that accepts every [`ImageFormat`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the formats that are accepted.
+    #[must_use]
+    pub fn allowed(mut self, formats: &[ImageFormat]) -> Self {
+        self.allowed = formats.to_vec();
+        self
+    }
+
+    /// Whether the given format is accepted.
+    #[must_use]
+    pub fn allows(&self, format: ImageFormat) -> bool {
+        self.allowed.contains(&format)
+    }
+
+    /// Validates that the given URL leads to an accepted image.
+    ///
+    /// # Errors
+    /// - [`Error::LinkIsNotImage`] if the format couldn't be recognised
+    /// - [`Error::UnsupportedFormat`] if the format was recognised, but not allowed
+    /// - [`Error::Generic`] if the ranged `GET` fails
+    pub async fn validate(&self, client: &Client, url: &str) -> Result<ImageFormat, Error> {
+        if let Some(format) = Self::check_head(client, url).await {
+            return self.accept(format);
+        }
+
+        debug!("HEAD was inconclusive, sniffing the first bytes");
+
+        let resp = client
+            .get(url)
+            .header(header::RANGE, format!("bytes=0-{}", SNIFF_LEN - 1))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // Servers are free to ignore the range, so only read what's needed.
+        let bytes = read_body(resp, SNIFF_LEN as u64).await?;
+
+        let format = ImageFormat::sniff(&bytes).ok_or(Error::LinkIsNotImage)?;
+
+        self.accept(format)
+    }
+
+    /// Sends a `HEAD` request, and returns the format if the `Content-Type` names one.
+    async fn check_head(client: &Client, url: &str) -> Option<ImageFormat> {
+        let head = client.head(url).send().await.ok()?;
+
+        if !head.status().is_success() {
+            debug!(status = %head.status(), "HEAD was rejected");
+            return None;
+        }
+
+        let content_type = head.headers().get(header::CONTENT_TYPE)?.to_str().ok()?;
+
+        ImageFormat::from_mime(content_type)
+    }
+
+    fn accept(&self, format: ImageFormat) -> Result<ImageFormat, Error> {
+        if self.allows(format) {
+            Ok(format)
+        } else {
+            Err(Error::UnsupportedFormat(format))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageFormat;
+
+    /// An ISO-BMFF `ftyp` box with the given major and compatible brands.
+    fn ftyp(major: &[u8], compatible: &[&[u8]]) -> Vec<u8> {
+        let size = u32::try_from(16 + 4 * compatible.len()).unwrap_or(u32::MAX);
+
+        let mut bytes = size.to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"ftyp");
+        bytes.extend_from_slice(major);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            bytes.extend_from_slice(brand);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn sniffs_every_magic_number() {
+        let cases: &[(&[u8], ImageFormat)] = &[
+            (&[0xFF, 0xD8, 0xFF, 0xE0, 0x00], ImageFormat::Jpeg),
+            (&[0xFF, 0xD8, 0xFF, 0xE1], ImageFormat::Jpeg),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", ImageFormat::Png),
+            (b"GIF87a\x01\x00", ImageFormat::Gif),
+            (b"GIF89a\x01\x00", ImageFormat::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", ImageFormat::WebP),
+        ];
+
+        for (bytes, format) in cases {
+            assert_eq!(ImageFormat::sniff(bytes), Some(*format), "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn sniffs_avif_by_major_or_compatible_brand() {
+        assert_eq!(
+            ImageFormat::sniff(&ftyp(b"avif", &[b"mif1"])),
+            Some(ImageFormat::Avif)
+        );
+        assert_eq!(
+            ImageFormat::sniff(&ftyp(b"avis", &[])),
+            Some(ImageFormat::Avif)
+        );
+        assert_eq!(
+            ImageFormat::sniff(&ftyp(b"mif1", &[b"miaf", b"avif"])),
+            Some(ImageFormat::Avif)
+        );
+    }
+
+    #[test]
+    fn rejects_heic_and_other_ftyp_files() {
+        assert_eq!(
+            ImageFormat::sniff(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            None
+        );
+        assert_eq!(
+            ImageFormat::sniff(&ftyp(b"isom", &[b"iso2", b"mp41"])),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_brands_past_the_end_of_the_box() {
+        // Claims to be 16 bytes long, so the trailing `avif` belongs to the next box
+        let mut bytes = ftyp(b"heic", &[]);
+        bytes.extend_from_slice(b"avif");
+
+        assert_eq!(ImageFormat::sniff(&bytes), None);
+    }
+
+    #[test]
+    fn rejects_riff_files_that_arent_webp() {
+        assert_eq!(ImageFormat::sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(ImageFormat::sniff(b"RIFF\x24\0\0\0AVI LIST"), None);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let cases: &[&[u8]] = &[
+            b"",
+            &[0xFF, 0xD8],
+            b"\x89PNG\r\n",
+            b"GIF89",
+            b"RIFF\x24\0\0\0WEB",
+            b"\0\0\0\x18fty",
+            b"\0\0\0\x18ftyp",
+        ];
+
+        for bytes in cases {
+            assert_eq!(ImageFormat::sniff(bytes), None, "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn reads_mime_types() {
+        let cases = [
+            ("image/jpeg", Some(ImageFormat::Jpeg)),
+            ("image/jpg", Some(ImageFormat::Jpeg)),
+            ("IMAGE/PNG", Some(ImageFormat::Png)),
+            ("image/gif; charset=binary", Some(ImageFormat::Gif)),
+            (" image/webp ", Some(ImageFormat::WebP)),
+            ("image/avif", Some(ImageFormat::Avif)),
+            ("image/heic", None),
+            ("application/octet-stream", None),
+            ("", None),
+        ];
+
+        for (mime, format) in cases {
+            assert_eq!(ImageFormat::from_mime(mime), format, "{mime}");
+        }
+    }
+}
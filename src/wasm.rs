@@ -0,0 +1,183 @@
+use std::{collections::HashMap, rc::Rc};
+
+use js_sys::Promise;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+use crate::{
+    aggregate::{Aggregator, Checked, source_by_name},
+    source::{Output, SourceId},
+};
+
+/// An [`Aggregator`] for JavaScript, exported as `Aggregator`.
+///
+/// ```js
+/// const aggregator = await Aggregator.create(["iqdb", "saucenao"], { saucenao: "..." });
+/// const results = await aggregator.check("https://i.imgur.com/vRsNUMS.jpg");
+/// ```
+#[wasm_bindgen(js_name = Aggregator)]
+#[derive(Debug, Clone)]
+pub struct WasmAggregator {
+    inner: Rc<Aggregator>,
+}
+
+/// The result of searching one source, as handed to JavaScript.
+#[derive(Debug, Serialize)]
+struct Record {
+    source: SourceId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<Checked> for Record {
+    fn from((source, res): Checked) -> Self {
+        match res {
+            Ok(output) => Self {
+                source,
+                output: Some(output),
+                error: None,
+            },
+            Err(e) => Self {
+                source,
+                output: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// The only setting the source called `name` takes, if it takes exactly one.
+fn single_key(name: &str) -> Option<&'static str> {
+    match name {
+        "saucenao" => Some("saucenao_api_key"),
+        "fuzzysearch" => Some("fuzzysearch_api_key"),
+        "iqdb_server" => Some("iqdb_server_url"),
+        "local" => Some("local_index"),
+        "tracemoe" => Some("tracemoe_api_key"),
+        _ => None,
+    }
+}
+
+/// Looks up `setting` for the source called `name`, falling back to the key under
+/// the source's own name when that's the only setting it takes.
+fn lookup(keys: &HashMap<String, String>, name: &str, setting: &str) -> Option<String> {
+    keys.get(setting)
+        .or_else(|| {
+            if single_key(name) == Some(setting) {
+                keys.get(name)
+            } else {
+                None
+            }
+        })
+        .cloned()
+}
+
+#[wasm_bindgen(js_class = Aggregator)]
+impl WasmAggregator {
+    /// Sets up the named sources, resolving to an `Aggregator`.
+    ///
+    /// `keys` is an object holding any setting [`source_by_name`] asks for, such as `ehentai_member_id`.
+    /// Sources that take a single key or URL can have it under their own name instead.
+    pub fn create(sources: Vec<String>, keys: JsValue) -> Promise {
+        future_to_promise(async move {
+            let keys: Option<HashMap<String, String>> = serde_wasm_bindgen::from_value(keys)?;
+            let keys = keys.unwrap_or_default();
+
+            let mut aggregator = Aggregator::new();
+            for name in &sources {
+                let source = source_by_name(name, |setting| lookup(&keys, name, setting))
+                    .await
+                    .map_err(|e| JsError::new(&format!("Couldn't set up `{name}`: {e}")))?;
+
+                aggregator = aggregator.with_boxed_source(source);
+            }
+
+            Ok(Self {
+                inner: Rc::new(aggregator),
+            }
+            .into())
+        })
+    }
+
+    /// The names of the sources that will be searched.
+    #[must_use]
+    pub fn sources(&self) -> Vec<String> {
+        self.inner.sources().map(ToString::to_string).collect()
+    }
+
+    /// Searches every source for `url`, resolving to an array of `{ source, output }` or `{ source, error }`.
+    pub fn check(&self, url: String) -> Promise {
+        let inner = Rc::clone(&self.inner);
+
+        future_to_promise(async move {
+            let results = inner
+                .check(&url)
+                .await
+                .map_err(|e| JsError::new(&e.to_string()))?;
+
+            let records = results.into_iter().map(Record::from).collect::<Vec<_>>();
+
+            Ok(records.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::lookup;
+
+    fn settings(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn single_key_sources_fall_back_to_their_name() {
+        let keys = settings(&[("saucenao", "abc"), ("iqdb_server", "http://localhost")]);
+
+        assert_eq!(
+            lookup(&keys, "saucenao", "saucenao_api_key").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(
+            lookup(&keys, "iqdb_server", "iqdb_server_url").as_deref(),
+            Some("http://localhost")
+        );
+    }
+
+    #[test]
+    fn named_settings_come_first() {
+        let keys = settings(&[("saucenao", "abc"), ("saucenao_api_key", "def")]);
+
+        assert_eq!(
+            lookup(&keys, "saucenao", "saucenao_api_key").as_deref(),
+            Some("def")
+        );
+    }
+
+    #[test]
+    fn multi_key_sources_need_named_settings() {
+        let keys = settings(&[("ehentai", "12345")]);
+
+        assert_eq!(lookup(&keys, "ehentai", "ehentai_member_id"), None);
+        assert_eq!(lookup(&keys, "ehentai", "ehentai_pass_hash"), None);
+
+        let keys = settings(&[
+            ("ehentai", "12345"),
+            ("ehentai_member_id", "1"),
+            ("ehentai_pass_hash", "2"),
+        ]);
+        assert_eq!(
+            lookup(&keys, "ehentai", "ehentai_member_id").as_deref(),
+            Some("1")
+        );
+        assert_eq!(lookup(&keys, "ehentai", "ehentai_igneous"), None);
+    }
+}